@@ -0,0 +1,46 @@
+//! Async access to a shared Neemo instance for tokio applications.
+//!
+//! Neemo's operations block on disk I/O, so each wrapper here runs the
+//! synchronous method on tokio's blocking thread pool and awaits the result,
+//! leaving the async executor free in the meantime.
+
+use crate::{Document, KvStore, Neemo, NeemoError};
+use serde_json::Value;
+use std::io;
+use std::sync::Arc;
+
+/// Runs `f` on the blocking thread pool. A panic in `f` is reported as an
+/// I/O error.
+async fn run_blocking<T, F>(f: F) -> Result<T, NeemoError>
+where
+    F: FnOnce() -> Result<T, NeemoError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(io::Error::from)?
+}
+
+impl<S: KvStore + 'static> Neemo<S> {
+    /// Retrieves a document by key without blocking the executor.
+    pub async fn get_async(self: &Arc<Self>, key: &str) -> Result<Option<Document>, NeemoError> {
+        let (neemo, key) = (Arc::clone(self), key.to_string());
+        run_blocking(move || neemo.get_result(&key)).await
+    }
+
+    /// Inserts or updates a document without blocking the executor.
+    pub async fn insert_async(self: &Arc<Self>, key: &str, doc: Document) -> Result<(), NeemoError> {
+        let (neemo, key) = (Arc::clone(self), key.to_string());
+        run_blocking(move || neemo.insert(&key, doc)).await
+    }
+
+    /// Deletes a document by key without blocking the executor.
+    pub async fn delete_async(self: &Arc<Self>, key: &str) -> Result<(), NeemoError> {
+        let (neemo, key) = (Arc::clone(self), key.to_string());
+        run_blocking(move || neemo.delete(&key)).await
+    }
+
+    /// Queries documents by a field-value pair without blocking the executor.
+    pub async fn query_async(self: &Arc<Self>, field: &str, value: Value) -> Result<Vec<Document>, NeemoError> {
+        let (neemo, field) = (Arc::clone(self), field.to_string());
+        run_blocking(move || neemo.query(&field, value)).await
+    }
+}
@@ -0,0 +1,4309 @@
+//! Neemo, a lightweight document database built on sled.
+//!
+//! Documents are JSON objects stored under string keys, with optional field
+//! indexes, a full-text index, expiry times, blobs and version history. The
+//! `neemo` binary is an interactive shell over this library.
+
+use sled::Db;
+use sled::Transactional;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use serde::{Serialize, Deserialize};
+use serde_json::{self, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::fmt;
+use std::ops::Bound;
+use std::io::{self, Write, BufReader, BufRead};
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use log::{debug, error, warn};
+#[cfg(feature = "flate2")]
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+
+#[cfg(all(unix, feature = "uds"))]
+pub mod uds;
+#[cfg(feature = "tokio")]
+pub mod async_api;
+
+/// Represents a document in Neemo.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Document {
+    pub data: HashMap<String, Value>,
+}
+
+impl Document {
+    /// Returns the value at `path`, where each dot steps into a nested
+    /// object, as in `address.city`. A top-level field named `path` is
+    /// preferred.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        if let Some(value) = self.data.get(path) {
+            return Some(value);
+        }
+        let mut parts = path.split('.');
+        let mut value = self.data.get(parts.next()?)?;
+        for part in parts {
+            value = value.as_object()?.get(part)?;
+        }
+        Some(value)
+    }
+
+    /// Returns a document holding only the given fields, leaving out those
+    /// this document lacks. A dotted path is kept under the path itself, as
+    /// in `"address.city": "NYC"`, which `get_path` still finds.
+    pub fn project(&self, fields: &[&str]) -> Document {
+        let data = fields.iter()
+            .filter_map(|field| Some((field.to_string(), self.get_path(field)?.clone())))
+            .collect();
+        Document { data }
+    }
+}
+
+/// Where documents whose field is `null` or missing are placed in sorted
+/// output and open-ended ranges. Both count as having no value; an explicit
+/// `null` is placed ahead of a missing field.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullOrder {
+    First,
+    #[default]
+    Last,
+}
+
+/// One line of an export: a document and the key it is stored under.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportRecord {
+    pub key: String,
+    pub doc: Document,
+}
+
+/// JSON type a schema can require of a field.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    String,
+    Number,
+    Bool,
+    Array,
+    Object,
+    Null,
+}
+
+impl FieldType {
+    /// Returns true if `value` is of this type.
+    pub fn matches(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (FieldType::String, Value::String(_))
+                | (FieldType::Number, Value::Number(_))
+                | (FieldType::Bool, Value::Bool(_))
+                | (FieldType::Array, Value::Array(_))
+                | (FieldType::Object, Value::Object(_))
+                | (FieldType::Null, Value::Null)
+        )
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldType::String => "a string",
+            FieldType::Number => "a number",
+            FieldType::Bool => "a boolean",
+            FieldType::Array => "an array",
+            FieldType::Object => "an object",
+            FieldType::Null => "null",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Fields required of the documents stored under keys starting with
+/// `key_prefix`. An empty prefix applies to every document.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Schema {
+    pub key_prefix: String,
+    pub required: BTreeMap<String, FieldType>,
+}
+
+impl Schema {
+    /// Creates a schema for keys starting with `key_prefix` that requires
+    /// no fields yet.
+    pub fn new(key_prefix: &str) -> Self {
+        Schema { key_prefix: key_prefix.to_string(), required: BTreeMap::new() }
+    }
+
+    /// Requires `field`, a top-level field or dotted path, to hold a value
+    /// of type `field_type`.
+    pub fn require(mut self, field: &str, field_type: FieldType) -> Self {
+        self.required.insert(field.to_string(), field_type);
+        self
+    }
+
+    /// Checks a document bound for `key` against the schema.
+    fn validate(&self, key: &str, doc: &Document) -> Result<(), NeemoError> {
+        if !key.starts_with(&self.key_prefix) {
+            return Ok(());
+        }
+        for (field, field_type) in &self.required {
+            match doc.get_path(field) {
+                Some(value) if field_type.matches(value) => {}
+                Some(_) => {
+                    let message = format!("field '{}' of document '{}' must be {}", field, key, field_type);
+                    return Err(NeemoError::Validation(message));
+                }
+                None => {
+                    let message = format!("document '{}' is missing required field '{}'", key, field);
+                    return Err(NeemoError::Validation(message));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Database settings carried alongside exported documents.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Metadata {
+    pub id_field: Option<String>,
+    pub index_value_cap: Option<usize>,
+    #[serde(default)]
+    pub null_order: NullOrder,
+    #[serde(default)]
+    pub indexes: Vec<String>,
+    #[serde(default)]
+    pub schemas: Vec<Schema>,
+    #[serde(default)]
+    pub history_limit: Option<usize>,
+}
+
+/// Outcome of a batched import, including where it stopped if it failed.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Documents written by committed batches.
+    pub imported: usize,
+    /// Lines in committed batches that were not valid documents.
+    pub skipped: usize,
+    /// Number of input lines fully committed; resume after this line.
+    pub last_committed_line: usize,
+    /// The error that stopped the import early, if any.
+    pub error: Option<NeemoError>,
+}
+
+/// Documents matched by a query along with how the query performed.
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    /// Matching documents with their keys.
+    pub documents: Vec<(String, Document)>,
+    /// Indexed keys or, for a full scan, documents examined.
+    pub scanned: usize,
+    /// Number of documents returned.
+    pub matched: usize,
+    /// Time taken by the query.
+    pub elapsed: Duration,
+}
+
+/// A condition added to a [`QueryBuilder`].
+#[derive(Debug, Clone)]
+enum Condition {
+    /// The field equals the value.
+    Eq(String, Value),
+    /// The field is greater than the value and of the same type.
+    Gt(String, Value),
+    /// The field lies in `start..end`, as in `range_query`.
+    Range(String, Value, Value),
+}
+
+impl Condition {
+    fn matches(&self, doc: &Document, nulls: NullOrder) -> bool {
+        match self {
+            Condition::Eq(field, value) => doc.get_path(field) == Some(value),
+            Condition::Gt(field, value) => doc.get_path(field).is_some_and(|found| {
+                value_rank(found) == value_rank(value) && compare_values(found, value).is_gt()
+            }),
+            Condition::Range(field, start, end) => match doc.get_path(field) {
+                None | Some(Value::Null) => match nulls {
+                    NullOrder::First => start.is_null(),
+                    NullOrder::Last => end.is_null(),
+                },
+                Some(value) => in_range(value, start, end, RangeEnd::Excluded),
+            },
+        }
+    }
+}
+
+/// Builds a query from several conditions, obtained from
+/// [`Neemo::query_builder`]. Documents must meet every condition.
+pub struct QueryBuilder<'a, S: KvStore = Db> {
+    neemo: &'a Neemo<S>,
+    conditions: Vec<Condition>,
+    sort_keys: Vec<(String, bool)>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl<'a, S: KvStore> QueryBuilder<'a, S> {
+    /// Keeps documents whose `field` equals `value`.
+    pub fn eq(mut self, field: &str, value: Value) -> Self {
+        self.conditions.push(Condition::Eq(field.to_string(), value));
+        self
+    }
+
+    /// Keeps documents whose `field` is greater than `value` and of the same
+    /// type, as `query_gt` does.
+    pub fn gt(mut self, field: &str, value: Value) -> Self {
+        self.conditions.push(Condition::Gt(field.to_string(), value));
+        self
+    }
+
+    /// Keeps documents whose `field` lies in `start..end`, as `range_query`
+    /// does. A `null` bound leaves that side open.
+    pub fn range(mut self, field: &str, start: Value, end: Value) -> Self {
+        self.conditions.push(Condition::Range(field.to_string(), start, end));
+        self
+    }
+
+    /// Returns at most `limit` documents.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first `offset` matching documents.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sorts the matches by `field`, ascending if `ascending`. Later calls
+    /// break ties left by earlier ones.
+    pub fn sort_by(mut self, field: &str, ascending: bool) -> Self {
+        self.sort_keys.push((field.to_string(), !ascending));
+        self
+    }
+
+    /// Runs the query. Equality conditions on indexed fields are answered
+    /// by intersecting their index entries; failing that, the first range
+    /// or greater-than condition on an indexed field reads the index, and
+    /// otherwise every document is scanned. Unsorted matches come in key
+    /// order, or in the order of the field whose index was read for a range.
+    pub fn execute(self) -> Result<Vec<Document>, NeemoError> {
+        let neemo = self.neemo;
+        let nulls = *lock(&neemo.null_order);
+
+        let mut keys: Option<BTreeSet<String>> = None;
+        for condition in &self.conditions {
+            if let Condition::Eq(field, value) = condition {
+                if !neemo.is_indexed(field) {
+                    continue;
+                }
+                let found: BTreeSet<String> = neemo.indexed_keys(field, value)?.unwrap_or_default().into_iter().collect();
+                keys = Some(match keys {
+                    Some(current) => current.intersection(&found).cloned().collect(),
+                    None => found,
+                });
+            }
+        }
+        let indexed_range = self.conditions.iter().find(|condition| match condition {
+            Condition::Gt(field, _) | Condition::Range(field, _, _) => neemo.is_indexed(field),
+            Condition::Eq(..) => false,
+        });
+        let candidates: Vec<Document> = match (keys, indexed_range) {
+            (Some(keys), _) => {
+                let mut docs = Vec::with_capacity(keys.len());
+                for key in &keys {
+                    docs.extend(neemo.get_readable(key)?);
+                }
+                docs
+            }
+            (None, Some(Condition::Gt(field, value))) => neemo.query_gt(field, value.clone()),
+            (None, Some(Condition::Range(field, start, end))) => neemo.range_query(field, start.clone(), end.clone())?,
+            _ => neemo.readable_documents().map(|item| item.map(|(_, doc)| doc)).collect::<Result<_, _>>()?,
+        };
+
+        let mut matches: Vec<Document> = candidates.into_iter()
+            .filter(|doc| self.conditions.iter().all(|condition| condition.matches(doc, nulls)))
+            .collect();
+        if !self.sort_keys.is_empty() {
+            matches.sort_by(|a, b| {
+                self.sort_keys.iter()
+                    .map(|(key, desc)| compare_fields(a.get_path(key), b.get_path(key), *desc, nulls))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+        Ok(matches.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect())
+    }
+}
+
+/// How a query reaches its candidate documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMethod {
+    /// The keys stored in the field's index entry for the value are read.
+    IndexLookup,
+    /// Every document is read and compared.
+    FullScan,
+}
+
+/// How a query would run, as reported by [`Neemo::explain_query`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlan {
+    /// The queried field.
+    pub field: String,
+    /// Whether the field is indexed.
+    pub indexed: bool,
+    /// Documents the query would read: the keys in the index entry, or
+    /// every document for a scan. Some may turn out not to match.
+    pub candidates: usize,
+    /// How the candidates are found.
+    pub access: AccessMethod,
+}
+
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.access {
+            AccessMethod::IndexLookup => {
+                write!(f, "index lookup on '{}': {} candidate documents", self.field, self.candidates)
+            }
+            AccessMethod::FullScan => {
+                write!(f, "full scan for unindexed '{}': {} candidate documents", self.field, self.candidates)
+            }
+        }
+    }
+}
+
+/// Sizes of a database, as reported by [`Neemo::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeemoStats {
+    /// Number of stored documents, leaving out expired ones as `count` does.
+    pub documents: usize,
+    /// Number of index entries, one per indexed field value.
+    pub index_entries: usize,
+    /// Bytes the document store occupies on disk.
+    pub data_size: u64,
+    /// Bytes the index store occupies on disk.
+    pub index_size: u64,
+}
+
+impl fmt::Display for NeemoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} documents, {} index entries, {} bytes of data, {} bytes of index",
+            self.documents, self.index_entries, self.data_size, self.index_size
+        )
+    }
+}
+
+/// How a full-text search query is matched against string values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextMatch {
+    /// The query may appear anywhere, including inside a word.
+    #[default]
+    Substring,
+    /// The query's words must appear as consecutive whole words.
+    WholeWord,
+}
+
+/// Whether a range query's end value is part of the range. The start value
+/// always is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RangeEnd {
+    /// Values equal to the end are left out, as in `start..end`.
+    #[default]
+    Excluded,
+    /// Values equal to the end are returned, as in `start..=end`.
+    Included,
+}
+
+/// What an import does with a document whose key is already taken, either
+/// in the database or earlier in the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// The imported document replaces the existing one.
+    Overwrite,
+    /// The existing document is kept and the imported one is left out.
+    Skip,
+    /// The import stops with `NeemoError::DuplicateKey`.
+    Fail,
+}
+
+/// A change to a document, as reported by [`Neemo::watch`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeEvent {
+    /// A document was inserted or replaced.
+    Insert { key: String, doc: Document },
+    /// A document was deleted.
+    Delete { key: String },
+}
+
+/// Whether [`Neemo::upsert`] created a document or replaced one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertResult {
+    /// No document was stored under the key.
+    Created,
+    /// The document stored under the key was replaced.
+    Updated,
+}
+
+/// One write applied by [`Neemo::batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    /// Inserts or replaces the document under the key.
+    Insert { key: String, doc: Document },
+    /// Deletes the document under the key, if there is one.
+    Delete { key: String },
+}
+
+/// Results of a full-text search, possibly cut short by a limit.
+#[derive(Debug, Clone)]
+pub struct SearchResults {
+    pub documents: Vec<Document>,
+    pub truncated: bool,
+}
+
+/// Errors returned by Neemo operations.
+#[derive(Debug)]
+pub enum NeemoError {
+    /// No ID field has been configured with `set_id_field`.
+    IdFieldNotSet,
+    /// The document lacks the configured ID field.
+    MissingIdField(String),
+    /// A document is already stored under the key.
+    DuplicateKey(String),
+    /// No document is stored under the key.
+    NotFound(String),
+    /// The operation was cancelled by its caller.
+    Cancelled,
+    /// Reading or writing a file or socket failed.
+    Io(io::Error),
+    /// A remote server reported an error.
+    Remote(String),
+    /// The sled database failed.
+    Sled(sled::Error),
+    /// A document or settings file could not be (de)serialized.
+    Serde(serde_json::Error),
+    /// The record stored under the key is not a valid document.
+    Corrupt(String),
+    /// A TTL was given but the database has no expiry store.
+    ExpiryUnsupported,
+    /// A blob was given but the database has no blob store.
+    BlobsUnsupported,
+    /// A custom storage backend failed.
+    Storage(String),
+    /// A document does not satisfy the schema for its key.
+    Validation(String),
+    /// A field that must hold a number holds something else.
+    NotNumeric(String),
+    /// A write was attempted on a database opened with `open_read_only`.
+    ReadOnly,
+}
+
+impl fmt::Display for NeemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NeemoError::IdFieldNotSet => write!(f, "no ID field configured"),
+            NeemoError::MissingIdField(field) => write!(f, "document is missing ID field '{}'", field),
+            NeemoError::DuplicateKey(key) => write!(f, "key '{}' already exists", key),
+            NeemoError::NotFound(key) => write!(f, "key '{}' not found", key),
+            NeemoError::Cancelled => write!(f, "operation cancelled"),
+            NeemoError::Io(e) => write!(f, "I/O error: {}", e),
+            NeemoError::Remote(msg) => write!(f, "server error: {}", msg),
+            NeemoError::Sled(e) => write!(f, "database error: {}", e),
+            NeemoError::Serde(e) => write!(f, "serialization error: {}", e),
+            NeemoError::Corrupt(key) => write!(f, "document '{}' is corrupt", key),
+            NeemoError::ExpiryUnsupported => write!(f, "this database does not support expiry"),
+            NeemoError::BlobsUnsupported => write!(f, "this database does not store blobs"),
+            NeemoError::Storage(msg) => write!(f, "storage error: {}", msg),
+            NeemoError::Validation(msg) => write!(f, "validation failed: {}", msg),
+            NeemoError::NotNumeric(field) => write!(f, "field '{}' is not a number", field),
+            NeemoError::ReadOnly => write!(f, "the database is open read-only"),
+        }
+    }
+}
+
+impl std::error::Error for NeemoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NeemoError::Io(e) => Some(e),
+            NeemoError::Sled(e) => Some(e),
+            NeemoError::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NeemoError {
+    fn from(e: io::Error) -> Self {
+        NeemoError::Io(e)
+    }
+}
+
+impl From<sled::Error> for NeemoError {
+    fn from(e: sled::Error) -> Self {
+        NeemoError::Sled(e)
+    }
+}
+
+impl From<serde_json::Error> for NeemoError {
+    fn from(e: serde_json::Error) -> Self {
+        NeemoError::Serde(e)
+    }
+}
+
+/// Result type used by storage backends.
+pub type KvResult<T> = Result<T, NeemoError>;
+
+/// Iterator over key/value pairs produced by a storage backend.
+pub type KvIter<'a> = Box<dyn Iterator<Item = KvResult<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+/// Writes staged for a store, by key. A `None` value removes the key.
+pub type KvWrites = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Ordered key-value storage backing a Neemo database.
+///
+/// Neemo keeps documents, index entries and, optionally, the text index in
+/// separate stores. Keys are ordered bytewise, which the index relies on for
+/// prefix and range scans. Scans must not borrow the store, since a
+/// database's stores can be swapped out by `Neemo::checkpoint` while a scan
+/// of them is still being read.
+pub trait KvStore: Send + Sync {
+    /// Returns the value stored under `key`.
+    fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>>;
+    /// Returns true if a value is stored under `key`.
+    fn contains_key(&self, key: &[u8]) -> KvResult<bool> {
+        self.get(key).map(|value| value.is_some())
+    }
+    /// Stores `value` under `key`, returning the previous value.
+    fn insert(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>>;
+    /// Removes `key`, returning the previous value.
+    fn remove(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>>;
+    /// Atomically replaces the value under `key` if it currently equals `old`
+    /// (`None` meaning absent). Returns whether the swap happened.
+    fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> KvResult<bool>;
+    /// Iterates the entries whose key starts with `prefix`, in key order.
+    fn scan_prefix(&self, prefix: &[u8]) -> KvIter<'static>;
+    /// Iterates the entries whose key lies within the bounds, in key order.
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> KvIter<'static>;
+    /// Iterates every entry in key order.
+    fn iter(&self) -> KvIter<'static>;
+    /// Persists pending writes.
+    fn flush(&self) -> KvResult<()>;
+    /// Removes every entry.
+    fn clear(&self) -> KvResult<()> {
+        let keys: Vec<Vec<u8>> = self.iter().map(|item| item.map(|(key, _)| key)).collect::<Result<_, _>>()?;
+        for key in keys {
+            self.remove(&key)?;
+        }
+        Ok(())
+    }
+    /// Returns the bytes the store occupies on disk, or 0 if it is not persisted.
+    fn size_on_disk(&self) -> KvResult<u64> {
+        Ok(0)
+    }
+    /// Returns the number of entries.
+    fn len(&self) -> KvResult<usize> {
+        self.iter().try_fold(0, |count, item| item.map(|_| count + 1))
+    }
+    /// Returns true if the store holds no entries.
+    fn is_empty(&self) -> KvResult<bool> {
+        self.iter().next().transpose().map(|first| first.is_none())
+    }
+    /// Applies `writes` to this store and `other_writes` to `other` in one
+    /// transaction, so either all of them are stored or none are. Returns
+    /// false, having written nothing, if the backend cannot span both
+    /// stores, which is the default.
+    fn apply_together(&self, _writes: &KvWrites, _other: &Self, _other_writes: &KvWrites) -> KvResult<bool> {
+        Ok(false)
+    }
+}
+
+fn sled_iter(iter: sled::Iter) -> KvIter<'static> {
+    Box::new(iter.map(|item| item.map(|(k, v)| (k.to_vec(), v.to_vec())).map_err(NeemoError::Sled)))
+}
+
+impl KvStore for sled::Tree {
+    fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        sled::Tree::get(self, key).map(|v| v.map(|v| v.to_vec())).map_err(NeemoError::Sled)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> KvResult<bool> {
+        sled::Tree::contains_key(self, key).map_err(NeemoError::Sled)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        sled::Tree::insert(self, key, value).map(|v| v.map(|v| v.to_vec())).map_err(NeemoError::Sled)
+    }
+
+    fn remove(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        sled::Tree::remove(self, key).map(|v| v.map(|v| v.to_vec())).map_err(NeemoError::Sled)
+    }
+
+    fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> KvResult<bool> {
+        let result = sled::Tree::compare_and_swap(self, key, old, new).map_err(NeemoError::Sled)?;
+        Ok(result.is_ok())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> KvIter<'static> {
+        sled_iter(sled::Tree::scan_prefix(self, prefix))
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> KvIter<'static> {
+        sled_iter(sled::Tree::range::<&[u8], _>(self, (start, end)))
+    }
+
+    fn iter(&self) -> KvIter<'static> {
+        sled_iter(sled::Tree::iter(self))
+    }
+
+    fn flush(&self) -> KvResult<()> {
+        sled::Tree::flush(self).map(|_| ()).map_err(NeemoError::Sled)
+    }
+
+    fn clear(&self) -> KvResult<()> {
+        sled::Tree::clear(self).map_err(NeemoError::Sled)
+    }
+
+    fn len(&self) -> KvResult<usize> {
+        Ok(sled::Tree::len(self))
+    }
+
+    fn is_empty(&self) -> KvResult<bool> {
+        Ok(sled::Tree::is_empty(self))
+    }
+
+    fn apply_together(&self, writes: &KvWrites, other: &Self, other_writes: &KvWrites) -> KvResult<bool> {
+        let result = (self, other).transaction(|(tree, other_tree)| {
+            for (tree, writes) in [(tree, writes), (other_tree, other_writes)] {
+                for (key, value) in writes {
+                    match value {
+                        Some(value) => tree.insert(key.as_slice(), value.as_slice())?,
+                        None => tree.remove(key.as_slice())?,
+                    };
+                }
+            }
+            Ok::<_, ConflictableTransactionError<Infallible>>(())
+        });
+        match result {
+            Ok(()) => Ok(true),
+            // sled transactions only span trees of one database.
+            Err(TransactionError::Storage(sled::Error::Unsupported(_))) => Ok(false),
+            Err(TransactionError::Storage(e)) => Err(NeemoError::Sled(e)),
+            Err(TransactionError::Abort(never)) => match never {},
+        }
+    }
+}
+
+impl KvStore for Db {
+    fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        KvStore::get(&**self, key)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> KvResult<bool> {
+        KvStore::contains_key(&**self, key)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        KvStore::insert(&**self, key, value)
+    }
+
+    fn remove(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        KvStore::remove(&**self, key)
+    }
+
+    fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> KvResult<bool> {
+        KvStore::compare_and_swap(&**self, key, old, new)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> KvIter<'static> {
+        KvStore::scan_prefix(&**self, prefix)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> KvIter<'static> {
+        KvStore::range(&**self, start, end)
+    }
+
+    fn iter(&self) -> KvIter<'static> {
+        KvStore::iter(&**self)
+    }
+
+    fn flush(&self) -> KvResult<()> {
+        KvStore::flush(&**self)
+    }
+
+    fn clear(&self) -> KvResult<()> {
+        KvStore::clear(&**self)
+    }
+
+    fn size_on_disk(&self) -> KvResult<u64> {
+        Db::size_on_disk(self).map_err(NeemoError::Sled)
+    }
+
+    fn len(&self) -> KvResult<usize> {
+        KvStore::len(&**self)
+    }
+
+    fn is_empty(&self) -> KvResult<bool> {
+        KvStore::is_empty(&**self)
+    }
+
+    fn apply_together(&self, writes: &KvWrites, other: &Self, other_writes: &KvWrites) -> KvResult<bool> {
+        KvStore::apply_together(&**self, writes, &**other, other_writes)
+    }
+}
+
+/// A [`KvStore`] that can report writes to it as they happen.
+pub trait WatchStore {
+    /// Subscribes to inserts and removals of keys starting with `prefix`.
+    fn watch_prefix(&self, prefix: &[u8]) -> sled::Subscriber;
+}
+
+impl WatchStore for sled::Tree {
+    fn watch_prefix(&self, prefix: &[u8]) -> sled::Subscriber {
+        sled::Tree::watch_prefix(self, prefix)
+    }
+}
+
+impl WatchStore for Db {
+    fn watch_prefix(&self, prefix: &[u8]) -> sled::Subscriber {
+        WatchStore::watch_prefix(&**self, prefix)
+    }
+}
+
+/// In-memory storage backend, for tests and throwaway databases.
+#[derive(Default)]
+pub struct MemoryStore {
+    map: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn snapshot<'a>(entries: impl Iterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>) -> KvIter<'static> {
+        let entries: Vec<_> = entries.map(|(k, v)| Ok((k.clone(), v.clone()))).collect();
+        Box::new(entries.into_iter())
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        Ok(lock(&self.map).get(key).cloned())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> KvResult<bool> {
+        Ok(lock(&self.map).contains_key(key))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        Ok(lock(&self.map).insert(key.to_vec(), value.to_vec()))
+    }
+
+    fn remove(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        Ok(lock(&self.map).remove(key))
+    }
+
+    fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> KvResult<bool> {
+        let mut map = lock(&self.map);
+        if map.get(key).map(Vec::as_slice) != old {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => map.insert(key.to_vec(), value.to_vec()),
+            None => map.remove(key),
+        };
+        Ok(true)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> KvIter<'static> {
+        let map = lock(&self.map);
+        let start: Bound<&[u8]> = Bound::Included(prefix);
+        Self::snapshot(map.range::<[u8], _>((start, Bound::Unbounded)).take_while(|(k, _)| k.starts_with(prefix)))
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> KvIter<'static> {
+        let map = lock(&self.map);
+        let empty = match (start, end) {
+            (Bound::Included(s), Bound::Included(e)) => s > e,
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => s >= e,
+            _ => false,
+        };
+        if empty {
+            return Box::new(std::iter::empty());
+        }
+        Self::snapshot(map.range::<[u8], _>((start, end)))
+    }
+
+    fn iter(&self) -> KvIter<'static> {
+        Self::snapshot(lock(&self.map).iter())
+    }
+
+    fn flush(&self) -> KvResult<()> {
+        Ok(())
+    }
+
+    fn clear(&self) -> KvResult<()> {
+        lock(&self.map).clear();
+        Ok(())
+    }
+
+    fn len(&self) -> KvResult<usize> {
+        Ok(lock(&self.map).len())
+    }
+
+    fn is_empty(&self) -> KvResult<bool> {
+        Ok(lock(&self.map).is_empty())
+    }
+
+    fn apply_together(&self, writes: &KvWrites, other: &Self, other_writes: &KvWrites) -> KvResult<bool> {
+        let apply = |map: &mut BTreeMap<Vec<u8>, Vec<u8>>, writes: &KvWrites| {
+            for (key, value) in writes {
+                match value {
+                    Some(value) => map.insert(key.clone(), value.clone()),
+                    None => map.remove(key),
+                };
+            }
+        };
+        let mut map = lock(&self.map);
+        if std::ptr::eq(self, other) {
+            apply(&mut map, writes);
+            apply(&mut map, other_writes);
+        } else {
+            let mut other_map = lock(&other.map);
+            apply(&mut map, writes);
+            apply(&mut other_map, other_writes);
+        }
+        Ok(true)
+    }
+}
+
+/// The reads and writes that index maintenance makes, so entries can be
+/// updated in a store directly or staged in a [`StagedStore`].
+trait EntryStore {
+    fn read_entry(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>>;
+    fn write_entry(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>>;
+    fn remove_entry(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>>;
+}
+
+impl<S: KvStore + ?Sized> EntryStore for S {
+    fn read_entry(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        KvStore::get(self, key)
+    }
+
+    fn write_entry(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        KvStore::insert(self, key, value)
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        KvStore::remove(self, key)
+    }
+}
+
+/// A store with writes held back until they are committed. Reads see the
+/// staged writes.
+struct StagedStore<'a, S: KvStore> {
+    store: &'a S,
+    writes: RefCell<KvWrites>,
+}
+
+impl<'a, S: KvStore> StagedStore<'a, S> {
+    fn new(store: &'a S) -> Self {
+        StagedStore { store, writes: RefCell::new(BTreeMap::new()) }
+    }
+
+    fn into_writes(self) -> KvWrites {
+        self.writes.into_inner()
+    }
+}
+
+impl<S: KvStore> EntryStore for StagedStore<'_, S> {
+    fn read_entry(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        match self.writes.borrow().get(key) {
+            Some(staged) => Ok(staged.clone()),
+            None => self.store.get(key),
+        }
+    }
+
+    fn write_entry(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        let previous = self.read_entry(key)?;
+        self.writes.borrow_mut().insert(key.to_vec(), Some(value.to_vec()));
+        Ok(previous)
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        let previous = self.read_entry(key)?;
+        self.writes.borrow_mut().insert(key.to_vec(), None);
+        Ok(previous)
+    }
+}
+
+/// Documents as seen by a `transaction` closure. Writes are held back until
+/// the closure succeeds, and reads see the closure's own earlier writes.
+pub struct Transaction<'a, S: KvStore> {
+    neemo: &'a Neemo<S>,
+    writes: RefCell<BTreeMap<String, Option<Document>>>,
+}
+
+impl<S: KvStore> Transaction<'_, S> {
+    /// Returns the document stored under `key`. An expired document is
+    /// returned as `None`.
+    pub fn get(&self, key: &str) -> Result<Option<Document>, NeemoError> {
+        if let Some(staged) = self.writes.borrow().get(key) {
+            return Ok(staged.clone());
+        }
+        if self.neemo.is_expired(key.as_bytes()) {
+            return Ok(None);
+        }
+        match self.neemo.stores().db.get(key.as_bytes())? {
+            Some(data) => serde_json::from_slice(&data).map(Some).map_err(|_| NeemoError::Corrupt(key.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts or updates a document, failing at once if it breaks a schema.
+    pub fn insert(&self, key: &str, doc: Document) -> Result<(), NeemoError> {
+        self.neemo.validate(key, &doc)?;
+        self.writes.borrow_mut().insert(key.to_string(), Some(doc));
+        Ok(())
+    }
+
+    /// Deletes a document, returning whether there was one.
+    pub fn delete(&self, key: &str) -> Result<bool, NeemoError> {
+        let existed = self.get(key)?.is_some();
+        self.writes.borrow_mut().insert(key.to_string(), None);
+        Ok(existed)
+    }
+}
+
+/// Least-recently-used cache of deserialized documents keyed by storage key.
+struct DocumentCache {
+    capacity: usize,
+    entries: HashMap<String, (Document, u64)>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    /// Counts invalidations, so a reader can tell whether a write landed
+    /// between reading a document from storage and caching it.
+    epoch: u64,
+}
+
+impl DocumentCache {
+    fn new(capacity: usize) -> Self {
+        DocumentCache { capacity, entries: HashMap::new(), recency: BTreeMap::new(), tick: 0, epoch: 0 }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Document> {
+        self.tick += 1;
+        let (doc, used) = self.entries.get_mut(key)?;
+        self.recency.remove(used);
+        *used = self.tick;
+        self.recency.insert(self.tick, key.to_string());
+        Some(doc.clone())
+    }
+
+    fn put(&mut self, key: &str, doc: Document) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.invalidate(key);
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.to_string());
+        self.entries.insert(key.to_string(), (doc, self.tick));
+    }
+
+    /// Caches `doc` read from storage, unless the cache was invalidated
+    /// since `epoch`, in which case `doc` may already be out of date.
+    fn fill(&mut self, key: &str, doc: Document, epoch: u64) {
+        if self.epoch == epoch {
+            self.put(key, doc);
+        }
+    }
+
+    fn invalidate(&mut self, key: &str) {
+        self.epoch += 1;
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.recency.remove(&used);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.epoch += 1;
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+/// Running totals for the numeric values of an aggregated field.
+#[derive(Default)]
+struct Accumulator {
+    sum: f64,
+    count: u64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: Option<&Value>) {
+        if let Some(f) = value.and_then(Value::as_f64) {
+            self.sum += f;
+            self.count += 1;
+            self.min = Some(self.min.map_or(f, |m| m.min(f)));
+            self.max = Some(self.max.map_or(f, |m| m.max(f)));
+        }
+    }
+
+    fn result(&self, op: &str) -> Option<Value> {
+        let number = match op {
+            "count" => return Some(Value::Number(self.count.into())),
+            // Without any numbers there is no sum or average to report.
+            _ if self.count == 0 => return None,
+            "sum" => self.sum,
+            "avg" => self.sum / self.count as f64,
+            "min" => self.min?,
+            "max" => self.max?,
+            _ => return None,
+        };
+        serde_json::Number::from_f64(number).map(Value::Number)
+    }
+}
+
+/// Settings for opening a database with [`Neemo::open_with_config`].
+///
+/// Each setting applies to every store of the database: documents, index,
+/// text index and expiry times.
+#[derive(Debug, Clone)]
+pub struct NeemoConfig {
+    cache_capacity: u64,
+    flush_every_ms: Option<u64>,
+    mode: sled::Mode,
+    compression: bool,
+}
+
+impl Default for NeemoConfig {
+    fn default() -> Self {
+        NeemoConfig {
+            cache_capacity: 1024 * 1024 * 1024,
+            flush_every_ms: Some(500),
+            mode: sled::Mode::LowSpace,
+            compression: false,
+        }
+    }
+}
+
+impl NeemoConfig {
+    /// Creates a configuration with sled's defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many bytes of each store sled keeps cached in memory.
+    /// Defaults to 1GiB.
+    pub fn cache_capacity(mut self, bytes: u64) -> Self {
+        self.cache_capacity = bytes;
+        self
+    }
+
+    /// Sets how often, in milliseconds, buffered writes are flushed to disk
+    /// in the background. `None` turns automatic flushing off, leaving it
+    /// to [`Neemo::flush`]. Defaults to every 500ms.
+    pub fn flush_every_ms(mut self, interval: Option<u64>) -> Self {
+        self.flush_every_ms = interval;
+        self
+    }
+
+    /// Chooses between `sled::Mode::LowSpace`, the default, which compacts
+    /// the files more eagerly, and `sled::Mode::HighThroughput`, which
+    /// writes faster at the cost of more disk space.
+    pub fn mode(mut self, mode: sled::Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Compresses stored pages with zstd. A database must always be opened
+    /// with the setting it was created with. Off by default.
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Opens the sled database at `path` with these settings.
+    fn open(&self, path: String) -> sled::Result<Db> {
+        sled::Config::new()
+            .path(path)
+            .cache_capacity(self.cache_capacity)
+            .flush_every_ms(self.flush_every_ms)
+            .mode(self.mode)
+            .use_compression(self.compression)
+            .open()
+    }
+}
+
+/// The stores of a database: documents, index entries, and those kept
+/// beside them.
+struct Stores<S> {
+    db: S,
+    index: S,
+    text: Option<S>,
+    expiry: Option<S>,
+    blobs: Option<S>,
+    history: Option<S>,
+}
+
+impl<S> Stores<S> {
+    /// Returns the optional stores kept beside the documents and index.
+    fn side_stores(&self) -> impl Iterator<Item = &S> {
+        self.text.iter().chain(&self.expiry).chain(&self.blobs).chain(&self.history)
+    }
+}
+
+impl Stores<Db> {
+    /// Opens the trees holding the collection called `name`.
+    fn collection(&self, name: &str) -> KvResult<Stores<sled::Tree>> {
+        let tree = format!("{}{}", COLLECTION_TREE_PREFIX, name);
+        let open = |store: &Db| store.open_tree(&tree);
+        Ok(Stores {
+            db: open(&self.db)?,
+            index: open(&self.index)?,
+            text: self.text.as_ref().map(open).transpose()?,
+            expiry: self.expiry.as_ref().map(open).transpose()?,
+            blobs: self.blobs.as_ref().map(open).transpose()?,
+            history: self.history.as_ref().map(open).transpose()?,
+        })
+    }
+}
+
+/// Represents the Neemo database.
+///
+/// Generic over its storage backend; sled is used unless another
+/// [`KvStore`] is supplied through [`Neemo::with_stores`].
+pub struct Neemo<S: KvStore = Db> {
+    /// Locked only long enough to clone the handle, so reads never wait on
+    /// each other; `checkpoint` replaces it while holding `writer`.
+    stores: RwLock<Arc<Stores<S>>>,
+    /// Held by every write so a document and its index entries change
+    /// together. Reads go straight to the stores without it.
+    writer: Mutex<()>,
+    db_path: String,
+    /// The settings the stores were opened with, reused when `checkpoint`
+    /// reopens them.
+    config: NeemoConfig,
+    id_field: Mutex<Option<String>>,
+    index_value_cap: Mutex<Option<usize>>,
+    unindexed_fields: Mutex<BTreeSet<String>>,
+    indexes: Mutex<BTreeSet<String>>,
+    null_order: Mutex<NullOrder>,
+    schemas: Mutex<Vec<Schema>>,
+    collections: Mutex<HashMap<String, Arc<Neemo<sled::Tree>>>>,
+    cache: Option<Mutex<DocumentCache>>,
+    history_limit: Mutex<Option<usize>>,
+    read_only: bool,
+}
+
+impl Neemo {
+    /// Creates a new Neemo instance.
+    ///
+    /// Panics if either tree cannot be opened; use [`Neemo::open`] to handle
+    /// the error instead.
+    pub fn new(path: &str) -> Self {
+        Neemo::open(path).expect("Failed to open Neemo database")
+    }
+
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &str) -> Result<Self, NeemoError> {
+        Neemo::open_with_config(path, NeemoConfig::default())
+    }
+
+    /// Opens the database at `path` with the given settings, creating it if
+    /// needed.
+    pub fn open_with_config(path: &str, config: NeemoConfig) -> Result<Self, NeemoError> {
+        finish_checkpoint(Path::new(path))?;
+        let db = config.open(format!("{}/data", path))?;
+        let index = config.open(format!("{}/index", path))?;
+        let text = config.open(format!("{}/text", path))?;
+        let expiry = config.open(format!("{}/expiry", path))?;
+        let blobs = config.open(format!("{}/blobs", path))?;
+        let history = config.open(format!("{}/history", path))?;
+        // Index stores from before explicit index definitions spell numbers
+        // in a form that does not sort numerically.
+        if index.get(INDEX_DEFINITIONS_KEY)?.is_none() {
+            upgrade_number_keys(&index)?;
+            save_index_definitions(&index, &load_index_definitions(&index))?;
+        }
+        // Index stores from before field names were escaped hold entries for
+        // fields containing `:` or `\` under the raw name.
+        let unescaped = load_index_definitions(&index).iter().any(|field| {
+            field_prefix(field) != format!("{}:", field)
+                && index.scan_prefix(format!("{}:", field).as_bytes()).next().is_some()
+        });
+        // Databases created before the text index existed get one now.
+        let build_text = KvStore::is_empty(&text)? && !KvStore::is_empty(&db)?;
+        let mut neemo = Neemo::with_stores(db, index)
+            .with_text_index(text)
+            .with_expiry_store(expiry)
+            .with_blob_store(blobs)
+            .with_history_store(history);
+        neemo.db_path = path.to_string();
+        neemo.config = config;
+        if unescaped {
+            neemo.rebuild_index()?;
+        } else if build_text {
+            neemo.rebuild_text_index()?;
+        }
+        Ok(neemo)
+    }
+
+    /// Opens the existing database at `path` for reading only. Writes,
+    /// including `import` and `restore`, fail with `NeemoError::ReadOnly`,
+    /// and the upgrades `open` applies to older databases are skipped, so a
+    /// database from before the text index is searched by scanning.
+    pub fn open_read_only(path: &str) -> Result<Self, NeemoError> {
+        if !Path::new(path).join("data").is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no database at '{}'", path)).into());
+        }
+        let config = NeemoConfig::default().flush_every_ms(None);
+        // A side store missing from an older database is left uncreated and
+        // read as empty.
+        let open_side = |tree: &str| -> sled::Result<Db> {
+            if Path::new(path).join(tree).is_dir() {
+                config.open(format!("{}/{}", path, tree))
+            } else {
+                sled::Config::new().temporary(true).open()
+            }
+        };
+        let db = config.open(format!("{}/data", path))?;
+        let index = config.open(format!("{}/index", path))?;
+        let text = open_side("text")?;
+        let expiry = open_side("expiry")?;
+        let blobs = open_side("blobs")?;
+        let history = open_side("history")?;
+        let text_built = !KvStore::is_empty(&text)? || KvStore::is_empty(&db)?;
+        let mut neemo = Neemo::with_stores(db, index)
+            .with_expiry_store(expiry)
+            .with_blob_store(blobs)
+            .with_history_store(history);
+        if text_built {
+            neemo = neemo.with_text_index(text);
+        }
+        neemo.db_path = path.to_string();
+        neemo.read_only = true;
+        Ok(neemo)
+    }
+
+    /// Supports backup and restore.
+    ///
+    /// Flushes both trees and copies their `data` and `index` directories,
+    /// along with document expiry times, blobs and history, into `path`,
+    /// which must not already hold a backup. Writes are blocked while the
+    /// copy runs.
+    pub fn backup(&self, path: &str) -> Result<(), NeemoError> {
+        let _writer = lock(&self.writer);
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        db.flush()?;
+        index.flush()?;
+        for store in [&stores.expiry, &stores.blobs, &stores.history].into_iter().flatten() {
+            store.flush()?;
+        }
+
+        for tree in ["data", "index", "expiry", "blobs", "history"] {
+            let target = Path::new(path).join(tree);
+            if target.exists() {
+                let message = format!("backup destination '{}' already exists", target.display());
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into());
+            }
+            copy_dir(&Path::new(&self.db_path).join(tree), &target)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of the database, collections included, with
+    /// those of a backup made by `backup`, including expiry times, blobs,
+    /// history and index definitions. Text indexes are rebuilt from the restored documents.
+    pub fn restore(&self, path: &str) -> Result<(), NeemoError> {
+        if self.read_only {
+            return Err(NeemoError::ReadOnly);
+        }
+        let mut backups = Vec::new();
+        for tree in ["data", "index"] {
+            let source = Path::new(path).join(tree);
+            if !source.is_dir() {
+                let message = format!("backup '{}' is missing", source.display());
+                return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+            }
+            backups.push(sled::open(source)?);
+        }
+        // Backups made before documents could expire, carry blobs or keep
+        // history lack those stores, which are then restored empty.
+        let mut side_backups = Vec::new();
+        for tree in ["expiry", "blobs", "history"] {
+            let source = Path::new(path).join(tree);
+            side_backups.push(if source.is_dir() { Some(sled::open(source)?) } else { None });
+        }
+
+        let collections: Vec<Arc<Neemo<sled::Tree>>> = lock(&self.collections).values().cloned().collect();
+        {
+            let _writer = self.write_lock()?;
+            let _collection_writers: Vec<_> = collections.iter().map(|collection| lock(&collection.writer)).collect();
+            let stores = self.stores();
+            restore_trees(&stores.db, Some(&backups[0]))?;
+            restore_trees(&stores.index, Some(&backups[1]))?;
+            for (store, backup) in [&stores.expiry, &stores.blobs, &stores.history].into_iter().zip(&side_backups) {
+                if let Some(store) = store {
+                    restore_trees(store, backup.as_ref())?;
+                }
+            }
+            *lock(&self.indexes) = load_index_definitions(&stores.index);
+            for collection in &collections {
+                *lock(&collection.indexes) = load_index_definitions(&collection.stores().index);
+            }
+        }
+        self.clear_cache();
+        self.rebuild_text_index()?;
+        for collection in &collections {
+            collection.clear_cache();
+            collection.rebuild_text_index()?;
+        }
+        Ok(())
+    }
+
+    /// Shrinks the files of the database, collections included, to the
+    /// space its documents need, without a restart.
+    ///
+    /// sled never hands back log space freed by deletes and overwrites, so
+    /// every store is flushed, exported into a fresh directory and swapped
+    /// in for the old one while writes wait, unless the copies would be no
+    /// smaller. Watches started before a checkpoint see no later changes. A checkpoint interrupted by a crash
+    /// is finished or undone the next time the database is opened.
+    pub fn checkpoint(&self) -> Result<(), NeemoError> {
+        if self.db_path.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "checkpoint needs a database opened from a path").into());
+        }
+        let path = Path::new(&self.db_path);
+        let collections: Vec<(String, Arc<Neemo<sled::Tree>>)> =
+            lock(&self.collections).iter().map(|(name, collection)| (name.clone(), Arc::clone(collection))).collect();
+        let _writer = self.write_lock()?;
+        let _collection_writers: Vec<_> = collections.iter().map(|(_, collection)| lock(&collection.writer)).collect();
+        let stores = self.stores();
+        let trees: Vec<(&str, &Db)> = [
+            ("data", Some(&stores.db)),
+            ("index", Some(&stores.index)),
+            ("text", stores.text.as_ref()),
+            ("expiry", stores.expiry.as_ref()),
+            ("blobs", stores.blobs.as_ref()),
+            ("history", stores.history.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(tree, store)| Some((tree, store?)))
+        .collect();
+
+        let (mut size_before, mut size_after) = (0, 0);
+        for (tree, store) in &trees {
+            store.flush()?;
+            size_before += store.size_on_disk()?;
+            let copy = self.config.open(checkpoint_dir(path, tree, "checkpoint").display().to_string())?;
+            copy.import(store.export());
+            copy.flush()?;
+            size_after += copy.size_on_disk()?;
+        }
+        if size_after >= size_before {
+            for (tree, _) in &trees {
+                std::fs::remove_dir_all(checkpoint_dir(path, tree, "checkpoint"))?;
+            }
+            return Ok(());
+        }
+        // Every copy is complete before the first swap, so an interrupted
+        // swap can always be finished from them.
+        for (tree, _) in &trees {
+            std::fs::rename(path.join(tree), checkpoint_dir(path, tree, "old"))?;
+            std::fs::rename(checkpoint_dir(path, tree, "checkpoint"), path.join(tree))?;
+        }
+
+        let reopen = |tree: &str| self.config.open(path.join(tree).display().to_string());
+        let reopened = Stores {
+            db: reopen("data")?,
+            index: reopen("index")?,
+            text: stores.text.as_ref().map(|_| reopen("text")).transpose()?,
+            expiry: stores.expiry.as_ref().map(|_| reopen("expiry")).transpose()?,
+            blobs: stores.blobs.as_ref().map(|_| reopen("blobs")).transpose()?,
+            history: stores.history.as_ref().map(|_| reopen("history")).transpose()?,
+        };
+        for (name, collection) in &collections {
+            *collection.stores.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(reopened.collection(name)?);
+        }
+        *self.stores.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(reopened);
+        for (tree, _) in &trees {
+            std::fs::remove_dir_all(checkpoint_dir(path, tree, "old"))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the collection called `name`, creating it on first use.
+    ///
+    /// A collection is a separate set of documents with its own keys,
+    /// indexes, settings and schemas, kept in trees of its own inside this
+    /// database's directory, so its keys never collide with the default
+    /// collection's or another collection's. Every call for the same name
+    /// returns the same handle.
+    pub fn collection(&self, name: &str) -> Result<Arc<Neemo<sled::Tree>>, NeemoError> {
+        let mut collections = lock(&self.collections);
+        if let Some(collection) = collections.get(name) {
+            return Ok(Arc::clone(collection));
+        }
+        // Opening a collection's trees creates them.
+        if self.read_only && !self.collection_names().iter().any(|existing| existing == name) {
+            return Err(NeemoError::ReadOnly);
+        }
+        let Stores { db, index, text, expiry, blobs, history } = self.stores().collection(name)?;
+        let mut collection = Neemo::with_stores(db, index);
+        let stores = collection.stores_mut();
+        (stores.text, stores.expiry, stores.blobs, stores.history) = (text, expiry, blobs, history);
+        collection.read_only = self.read_only;
+        let collection = Arc::new(collection);
+        collections.insert(name.to_string(), Arc::clone(&collection));
+        Ok(collection)
+    }
+
+    /// Returns the names of the collections stored in this database.
+    pub fn collection_names(&self) -> Vec<String> {
+        self.stores().db.tree_names()
+            .iter()
+            .filter_map(|tree| tree.strip_prefix(COLLECTION_TREE_PREFIX.as_bytes()))
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()
+    }
+}
+
+impl Neemo<MemoryStore> {
+    /// Creates a database that lives only in memory.
+    pub fn in_memory() -> Self {
+        Neemo::with_stores(MemoryStore::new(), MemoryStore::new())
+            .with_text_index(MemoryStore::new())
+            .with_expiry_store(MemoryStore::new())
+            .with_blob_store(MemoryStore::new())
+            .with_history_store(MemoryStore::new())
+    }
+}
+
+impl<S: KvStore> Neemo<S> {
+    /// Creates a Neemo instance over the given document and index stores.
+    ///
+    /// Index definitions are read from the index store. An index store
+    /// written before fields had to be indexed explicitly has every field it
+    /// holds entries for defined as an index.
+    pub fn with_stores(db: S, index: S) -> Self {
+        if let Err(e) = finish_pending_writes(&db, &index) {
+            error!("Failed to finish an interrupted commit: {}", e);
+        }
+        let indexes = load_index_definitions(&index);
+        let stores = Stores { db, index, text: None, expiry: None, blobs: None, history: None };
+        Neemo {
+            stores: RwLock::new(Arc::new(stores)),
+            writer: Mutex::new(()),
+            db_path: String::new(),
+            config: NeemoConfig::default(),
+            id_field: Mutex::new(None),
+            index_value_cap: Mutex::new(None),
+            unindexed_fields: Mutex::new(BTreeSet::new()),
+            indexes: Mutex::new(indexes),
+            null_order: Mutex::new(NullOrder::default()),
+            schemas: Mutex::new(Vec::new()),
+            collections: Mutex::new(HashMap::new()),
+            cache: None,
+            history_limit: Mutex::new(None),
+            read_only: false,
+        }
+    }
+
+    /// Keeps an inverted index in `text` mapping each lowercased word of the
+    /// documents' string values to the keys of the documents containing it,
+    /// so `full_text_search` reads only candidate documents. Without one,
+    /// every search scans all documents.
+    pub fn with_text_index(mut self, text: S) -> Self {
+        self.stores_mut().text = Some(text);
+        self
+    }
+
+    /// Keeps the expiry times set by `insert_with_ttl` in `expiry`.
+    pub fn with_expiry_store(mut self, expiry: S) -> Self {
+        self.stores_mut().expiry = Some(expiry);
+        self
+    }
+
+    /// Keeps the binary fields set by `insert_blob` in `blobs`.
+    pub fn with_blob_store(mut self, blobs: S) -> Self {
+        self.stores_mut().blobs = Some(blobs);
+        self
+    }
+
+    /// Keeps the past versions archived while `set_history_limit` is on in
+    /// `history`.
+    pub fn with_history_store(mut self, history: S) -> Self {
+        self.stores_mut().history = Some(history);
+        self
+    }
+
+    /// Returns the current stores. A checkpoint may swap in new ones
+    /// afterwards; a write holding `writer` always sees the current ones.
+    fn stores(&self) -> Arc<Stores<S>> {
+        Arc::clone(&self.stores.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns the stores of a handle still being built.
+    fn stores_mut(&mut self) -> &mut Stores<S> {
+        let stores = self.stores.get_mut().unwrap_or_else(PoisonError::into_inner);
+        Arc::get_mut(stores).expect("stores are not shared until the handle is built")
+    }
+
+    /// Keeps up to `capacity` recently read documents deserialized in memory.
+    /// Entries are filled by `get` and `query` and dropped when their key is
+    /// written or deleted.
+    pub fn with_document_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Mutex::new(DocumentCache::new(capacity)));
+        self
+    }
+
+    /// Drops a key from the document cache after it changes.
+    fn invalidate_cached(&self, key: &str) {
+        if let Some(cache) = &self.cache {
+            lock(cache).invalidate(key);
+        }
+    }
+
+    /// Empties the document cache after writes that bypass the indexed path.
+    fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            lock(cache).clear();
+        }
+    }
+
+    /// Inserts or updates a document.
+    ///
+    /// Index entries left by a document previously stored under the key are
+    /// removed when its values no longer appear in the new document.
+    pub fn insert(&self, key: &str, doc: Document) -> Result<(), NeemoError> {
+        let started = Instant::now();
+        self.validate(key, &doc)?;
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        self.write_document(db, index, key, &doc)?;
+        debug!("op=insert key={:?} count=1 elapsed_us={}", key, started.elapsed().as_micros());
+        Ok(())
+    }
+
+    /// Inserts or updates a document like `insert`, reporting which it did.
+    /// A document whose TTL has passed counts as absent.
+    pub fn upsert(&self, key: &str, doc: Document) -> Result<UpsertResult, NeemoError> {
+        self.validate(key, &doc)?;
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        let existed = db.contains_key(key.as_bytes())? && !self.is_expired(key.as_bytes());
+        self.write_document(db, index, key, &doc)?;
+        Ok(if existed { UpsertResult::Updated } else { UpsertResult::Created })
+    }
+
+    /// Inserts or updates a document that expires after `ttl`. Once expired it
+    /// is no longer returned by `get`, `query` or `list`; `get` deletes it,
+    /// and `purge_expired` removes every expired document.
+    pub fn insert_with_ttl(&self, key: &str, doc: Document, ttl: Duration) -> Result<(), NeemoError> {
+        let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        let stores = self.stores();
+        let expiry = stores.expiry.as_ref().ok_or(NeemoError::ExpiryUnsupported)?;
+        self.insert(key, doc)?;
+        expiry.insert(key.as_bytes(), &now_millis().saturating_add(ttl).to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Deletes every document whose TTL has passed, returning how many were
+    /// removed.
+    pub fn purge_expired(&self) -> Result<usize, NeemoError> {
+        let Some(expiry) = &self.stores().expiry else {
+            return Ok(0);
+        };
+        let now = now_millis();
+        let expired: Vec<String> = expiry.iter()
+            .flatten()
+            .filter(|(_, expires_at)| decode_expiry(expires_at) <= now)
+            .map(|(key, _)| String::from_utf8_lossy(&key).into_owned())
+            .collect();
+        for key in &expired {
+            self.delete(key)?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Returns true if the document under `key` has outlived its TTL.
+    fn is_expired(&self, key: &[u8]) -> bool {
+        let Some(expiry) = &self.stores().expiry else {
+            return false;
+        };
+        matches!(expiry.get(key), Ok(Some(expires_at)) if decode_expiry(&expires_at) <= now_millis())
+    }
+
+    /// Forgets the expiry time of the document under `key`.
+    fn clear_expiry(&self, key: &str) -> KvResult<()> {
+        if let Some(expiry) = &self.stores().expiry {
+            expiry.remove(key.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Stores `bytes` as the binary field `field` of the document under
+    /// `key`, which must exist. Blobs are kept as raw bytes beside the
+    /// document rather than encoded into its JSON, so they are not returned
+    /// by `get`, queried, indexed or exported; read them with `get_blob`.
+    /// They are deleted along with their document.
+    pub fn insert_blob(&self, key: &str, field: &str, bytes: &[u8]) -> Result<(), NeemoError> {
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let blobs = stores.blobs.as_ref().ok_or(NeemoError::BlobsUnsupported)?;
+        if !stores.db.contains_key(key.as_bytes())? {
+            return Err(NeemoError::NotFound(key.to_string()));
+        }
+        blobs.insert(&blob_key(key, field), bytes)?;
+        Ok(())
+    }
+
+    /// Returns the binary field `field` of the document under `key`, if set.
+    pub fn get_blob(&self, key: &str, field: &str) -> Result<Option<Vec<u8>>, NeemoError> {
+        match &self.stores().blobs {
+            Some(blobs) => Ok(blobs.get(&blob_key(key, field))?),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the binary field `field` of the document under `key`,
+    /// returning whether it was set.
+    pub fn delete_blob(&self, key: &str, field: &str) -> Result<bool, NeemoError> {
+        let _writer = self.write_lock()?;
+        match &self.stores().blobs {
+            Some(blobs) => Ok(blobs.remove(&blob_key(key, field))?.is_some()),
+            None => Ok(false),
+        }
+    }
+
+    /// Removes and returns every blob of the document under `key` as
+    /// `(field, bytes)` pairs.
+    fn take_blobs(&self, key: &str) -> KvResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let Some(blobs) = &self.stores().blobs else {
+            return Ok(Vec::new());
+        };
+        let prefix = blob_key(key, "");
+        let taken: Vec<(Vec<u8>, Vec<u8>)> = blobs.scan_prefix(&prefix).collect::<Result<_, _>>()?;
+        for (blob_key, _) in &taken {
+            blobs.remove(blob_key)?;
+        }
+        Ok(taken.into_iter().map(|(blob_key, bytes)| (blob_key[prefix.len()..].to_vec(), bytes)).collect())
+    }
+
+    /// Keeps up to `limit` past versions of each document: from now on,
+    /// every write replacing a document archives the version it replaces,
+    /// dropping the oldest beyond the limit. A limit of 0 archives nothing
+    /// and drops a document's past versions when it is next replaced.
+    /// `None`, the default, stops archiving; versions already archived are
+    /// kept.
+    pub fn set_history_limit(&self, limit: Option<usize>) {
+        *lock(&self.history_limit) = limit;
+    }
+
+    /// Returns the archived past versions of the document under `key`,
+    /// newest first. Versions outlive the document, so a deleted document's
+    /// history can still be read.
+    pub fn history(&self, key: &str) -> Vec<Document> {
+        let Some(history) = &self.stores().history else {
+            return Vec::new();
+        };
+        let mut versions: Vec<Document> = history.scan_prefix(&version_prefix(key))
+            .flatten()
+            .filter_map(|(_, doc_data)| serde_json::from_slice(&doc_data).ok())
+            .collect();
+        versions.reverse();
+        versions
+    }
+
+    /// Archives `old_data`, the stored version of the document under `key`
+    /// that a write is replacing, if history is kept.
+    fn archive(&self, key: &str, old_data: &[u8]) -> KvResult<()> {
+        let (Some(history), Some(limit)) = (&self.stores().history, *lock(&self.history_limit)) else {
+            return Ok(());
+        };
+        let prefix = version_prefix(key);
+        let versions: Vec<Vec<u8>> = history.scan_prefix(&prefix)
+            .map(|entry| entry.map(|(version_key, _)| version_key))
+            .collect::<Result<_, _>>()?;
+        let next = versions.last()
+            .and_then(|last| u64::from_str_radix(std::str::from_utf8(&last[prefix.len()..]).ok()?, 16).ok())
+            .map_or(0, |version| version + 1);
+        // A limit of 0 keeps no versions, not even the one being replaced.
+        let archived = limit > 0;
+        if archived {
+            history.insert(&[prefix, format!("{:016x}", next).into_bytes()].concat(), old_data)?;
+        }
+        for version_key in versions.iter().take((versions.len() + usize::from(archived)).saturating_sub(limit)) {
+            history.remove(version_key)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts or updates many documents while other writes wait.
+    /// When a key appears more than once its last document wins. The data
+    /// and index entries of the whole batch are committed together, and the
+    /// stores are flushed once at the end rather than per document.
+    pub fn insert_many(&self, docs: &[(String, Document)]) -> Result<(), NeemoError> {
+        let mut latest = BTreeMap::new();
+        for (key, doc) in docs {
+            self.validate(key, doc)?;
+            latest.insert(key.clone(), Some(doc.clone()));
+        }
+        let _writer = self.write_lock()?;
+        self.commit_documents(&latest)?;
+        let stores = self.stores();
+        stores.db.flush()?;
+        stores.index.flush()?;
+        if let Some(text) = &stores.text {
+            text.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Requires documents stored under the schema's key prefix to hold its
+    /// fields, replacing any schema set for the same prefix. Writes that
+    /// break a schema fail with `NeemoError::Validation`; documents already
+    /// stored are not checked.
+    pub fn set_schema(&self, schema: Schema) {
+        let mut schemas = lock(&self.schemas);
+        schemas.retain(|existing| existing.key_prefix != schema.key_prefix);
+        schemas.push(schema);
+    }
+
+    /// Removes the schema set for `key_prefix`, returning whether one was set.
+    pub fn remove_schema(&self, key_prefix: &str) -> bool {
+        let mut schemas = lock(&self.schemas);
+        let before = schemas.len();
+        schemas.retain(|existing| existing.key_prefix != key_prefix);
+        schemas.len() < before
+    }
+
+    /// Checks a document bound for `key` against every schema covering it.
+    fn validate(&self, key: &str, doc: &Document) -> Result<(), NeemoError> {
+        lock(&self.schemas).iter().try_for_each(|schema| schema.validate(key, doc))
+    }
+
+    /// Designates a field whose value becomes the storage key in `insert_doc`.
+    pub fn set_id_field(&self, field: &str) {
+        *lock(&self.id_field) = Some(field.to_string());
+    }
+
+    /// Inserts a document keyed by its ID field, returning the derived key.
+    /// Fails if a document already exists under that key.
+    pub fn insert_doc(&self, doc: Document) -> Result<String, NeemoError> {
+        let key = self.id_key(&doc)?;
+        self.validate(&key, &doc)?;
+        let serialized = serde_json::to_string(&doc)?;
+        let _writer = self.write_lock()?;
+        if !self.stores().db.compare_and_swap(key.as_bytes(), None, Some(serialized.as_bytes()))? {
+            return Err(NeemoError::DuplicateKey(key));
+        }
+        self.invalidate_cached(&key);
+        self.index_document(&key, &doc)?;
+        Ok(key)
+    }
+
+    /// Derives a document's storage key from the configured ID field.
+    fn id_key(&self, doc: &Document) -> Result<String, NeemoError> {
+        let field = lock(&self.id_field).clone().ok_or(NeemoError::IdFieldNotSet)?;
+        match doc.data.get(&field) {
+            Some(Value::String(id)) => Ok(id.clone()),
+            Some(id) => Ok(id.to_string()),
+            None => Err(NeemoError::MissingIdField(field)),
+        }
+    }
+
+    /// Caps how many bytes of an indexed string value are stored in its index
+    /// key; longer values are stored as that prefix plus a hash of the whole
+    /// value. Equality queries on such values then match by prefix and hash
+    /// and are confirmed against the document, and `facet` skips them. Set
+    /// this before inserting, as existing entries are not re-encoded.
+    pub fn set_index_value_cap(&self, cap: Option<usize>) {
+        *lock(&self.index_value_cap) = cap;
+    }
+
+    /// Returns the truncation point for a string value longer than the cap.
+    fn capped_len(&self, value: &Value) -> Option<usize> {
+        let cap = (*lock(&self.index_value_cap))?;
+        let encoded_len = value.as_str().map(|_| value.to_string().len())?;
+        (encoded_len > cap).then_some(cap)
+    }
+
+    /// Builds the index key for a field value.
+    fn index_key(&self, field: &str, value: &Value) -> String {
+        self.value_key(field_prefix(field), value)
+    }
+
+    /// Builds the key of the entry listing the documents whose array `field`
+    /// holds `element`.
+    fn element_key(&self, field: &str, element: &Value) -> String {
+        self.value_key(element_prefix(field), element)
+    }
+
+    /// Appends the encoding of `value` to the start of an index key.
+    fn value_key(&self, prefix: String, value: &Value) -> String {
+        let encoded = value.to_string();
+        match self.capped_len(value) {
+            Some(cap) => {
+                let mut end = cap;
+                while !encoded.is_char_boundary(end) {
+                    end -= 1;
+                }
+                format!("{}{}#{:016x}", prefix, &encoded[..end], fnv1a(encoded.as_bytes()))
+            }
+            None => match value {
+                Value::Number(n) => format!("{}{}{}", prefix, sortable_number(n), encoded),
+                _ => format!("{}{}", prefix, encoded),
+            },
+        }
+    }
+
+    /// Builds the index key a range bound starts from. A numeric bound
+    /// covers every spelling of the number, such as `5` and `5.0`.
+    fn range_bound_key(&self, field: &str, value: &Value) -> String {
+        match value {
+            Value::Number(n) => format!("{}{}", field_prefix(field), sortable_number(n)),
+            _ => self.index_key(field, value),
+        }
+    }
+
+    /// Adds a document's key to the index entry of each of its fields.
+    fn index_document(&self, key: &str, doc: &Document) -> Result<(), NeemoError> {
+        self.reindex(&self.stores().index, key, None, doc)
+    }
+
+    /// Replaces the document stored under `key`, which must exist. Only the
+    /// index entries of values that changed are touched: entries for old
+    /// values are dropped and entries for new ones added.
+    pub fn update(&self, key: &str, doc: Document) -> Result<(), NeemoError> {
+        self.modify(key, |stored| *stored = doc)
+    }
+
+    /// Sets the top-level fields of `fields` in the document stored under
+    /// `key`, which must exist, leaving its other fields alone. Index entries
+    /// are updated as in `update`.
+    pub fn patch(&self, key: &str, fields: Document) -> Result<(), NeemoError> {
+        self.modify(key, |stored| stored.data.extend(fields.data))
+    }
+
+    /// Adds `by` to the numeric `field` of the document under `key` and
+    /// returns the new value. A missing field, or a missing document, starts
+    /// from zero. The write is a `compare_and_swap` retried until no other
+    /// write intervened, so concurrent increments are never lost.
+    pub fn increment(&self, key: &str, field: &str, by: f64) -> Result<f64, NeemoError> {
+        loop {
+            let current = self.get_result(key)?;
+            let mut doc = current.clone().unwrap_or_else(|| Document { data: HashMap::new() });
+            let total = match doc.data.get(field) {
+                Some(value) => value.as_f64().ok_or_else(|| NeemoError::NotNumeric(field.to_string()))? + by,
+                None => by,
+            };
+            // Whole totals stay integers so counters read back as `3`, not `3.0`.
+            let number = if total.fract() == 0.0 && total.abs() < 2f64.powi(53) {
+                serde_json::Number::from(total as i64)
+            } else {
+                serde_json::Number::from_f64(total).ok_or_else(|| NeemoError::NotNumeric(field.to_string()))?
+            };
+            doc.data.insert(field.to_string(), Value::Number(number));
+            if self.compare_and_swap(key, current.as_ref(), Some(doc))? {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Deep-merges `patch` into the document stored under `key`, or stores
+    /// `patch` as is if there is none. Fields of `patch` replace the stored
+    /// ones, except that two objects are merged field by field, so nested
+    /// fields `patch` leaves out are kept. Index entries are updated as in
+    /// `update`.
+    pub fn merge(&self, key: &str, patch: Document) -> Result<(), NeemoError> {
+        self.write_with(key, |stored| {
+            let Some(stored) = stored else {
+                return Ok(patch);
+            };
+            let mut doc = stored.clone();
+            for (field, value) in patch.data {
+                match doc.data.get_mut(&field) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        doc.data.insert(field, value);
+                    }
+                }
+            }
+            Ok(doc)
+        })
+    }
+
+    /// Applies `change` to the document stored under `key`, which must exist,
+    /// and writes the result back. Other writes wait until it is stored, so
+    /// no concurrent change is lost.
+    fn modify<F>(&self, key: &str, change: F) -> Result<(), NeemoError>
+    where
+        F: FnOnce(&mut Document),
+    {
+        self.write_with(key, |stored| {
+            let mut doc = stored.cloned().ok_or_else(|| NeemoError::NotFound(key.to_string()))?;
+            change(&mut doc);
+            Ok(doc)
+        })
+    }
+
+    /// Stores the document `change` builds from the one stored under `key`,
+    /// if any, holding the writer lock throughout.
+    fn write_with<F>(&self, key: &str, change: F) -> Result<(), NeemoError>
+    where
+        F: FnOnce(Option<&Document>) -> Result<Document, NeemoError>,
+    {
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        let old: Option<Document> = match db.get(key.as_bytes())? {
+            Some(data) => Some(serde_json::from_slice(&data).map_err(|_| NeemoError::Corrupt(key.to_string()))?),
+            None => None,
+        };
+        let doc = change(old.as_ref())?;
+        self.validate(key, &doc)?;
+        let serialized = serde_json::to_string(&doc)?;
+        if let Some(old_data) = db.insert(key.as_bytes(), serialized.as_bytes())? {
+            self.archive(key, &old_data)?;
+        }
+        self.invalidate_cached(key);
+        self.reindex(index, key, old.as_ref(), &doc)?;
+        Ok(())
+    }
+
+    /// Stores `new` under `key`, or deletes the document when `new` is
+    /// `None`, but only if the stored document still equals `expected`
+    /// (`None` meaning no document is stored). Returns false and changes
+    /// nothing if the document differs, so a caller can re-read and retry.
+    /// Index entries are only touched when the swap succeeds.
+    pub fn compare_and_swap(&self, key: &str, expected: Option<&Document>, new: Option<Document>) -> Result<bool, NeemoError> {
+        if let Some(new) = &new {
+            self.validate(key, new)?;
+        }
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        let current_data = db.get(key.as_bytes())?;
+        let current: Option<Document> = match &current_data {
+            Some(data) => Some(serde_json::from_slice(data).map_err(|_| NeemoError::Corrupt(key.to_string()))?),
+            None => None,
+        };
+        if current.as_ref() != expected {
+            return Ok(false);
+        }
+        let serialized = new.as_ref().map(serde_json::to_vec).transpose()?;
+        if !db.compare_and_swap(key.as_bytes(), current_data.as_deref(), serialized.as_deref())? {
+            return Ok(false);
+        }
+        self.invalidate_cached(key);
+        if let (Some(old_data), Some(_)) = (&current_data, &new) {
+            self.archive(key, old_data)?;
+        }
+        match (current, new) {
+            (current, Some(new)) => self.reindex(index, key, current.as_ref(), &new)?,
+            (Some(old), None) => {
+                self.unindex_document(index, key, &old)?;
+                self.take_blobs(key)?;
+                self.clear_expiry(key)?;
+            }
+            (None, None) => {}
+        }
+        Ok(true)
+    }
+
+    /// Moves a document's index and text index entries from the values of
+    /// `old` to those of `new`, leaving unchanged values alone.
+    fn reindex(&self, index: &S, key: &str, old: Option<&Document>, new: &Document) -> KvResult<()> {
+        let old_keys = old.map(|old| self.document_index_keys(old)).unwrap_or_default();
+        if let Some(old) = old {
+            self.remove_stale_entries(index, key, old, new)?;
+        }
+        for index_key in self.document_index_keys(new).difference(&old_keys) {
+            add_index_key(index, index_key, key)?;
+        }
+        self.add_new_words(key, old, new)
+    }
+
+    /// Adds the text index entries of words in `new` that `old` lacks.
+    fn add_new_words(&self, key: &str, old: Option<&Document>, new: &Document) -> KvResult<()> {
+        if let Some(text) = &self.stores().text {
+            let old_words = old.map(document_words).unwrap_or_default();
+            for word in document_words(new).keys().filter(|word| !old_words.contains_key(*word)) {
+                add_index_key(text, word, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the index and text index entries of values in `old` that
+    /// `new` no longer holds.
+    fn remove_stale_entries(&self, index: &S, key: &str, old: &Document, new: &Document) -> KvResult<()> {
+        let new_keys = self.document_index_keys(new);
+        for index_key in self.document_index_keys(old).difference(&new_keys) {
+            remove_index_key(index, index_key, key)?;
+        }
+        self.remove_stale_words(key, old, new)
+    }
+
+    /// Removes the text index entries of words in `old` that `new` lacks.
+    fn remove_stale_words(&self, key: &str, old: &Document, new: &Document) -> KvResult<()> {
+        if let Some(text) = &self.stores().text {
+            let new_words = document_words(new);
+            for word in document_words(old).keys().filter(|word| !new_words.contains_key(*word)) {
+                remove_index_key(text, word, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a document's key to the text index entry of each of its words.
+    fn index_text(&self, key: &str, doc: &Document) -> KvResult<()> {
+        if let Some(text) = &self.stores().text {
+            for word in document_words(doc).keys() {
+                add_index_key(text, word, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a document's key from the text index entries of its words.
+    fn unindex_text(&self, key: &str, doc: &Document) -> KvResult<()> {
+        if let Some(text) = &self.stores().text {
+            for word in document_words(doc).keys() {
+                remove_index_key(text, word, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the text index from the stored documents.
+    fn rebuild_text_index(&self) -> KvResult<()> {
+        let _writer = lock(&self.writer);
+        let stores = self.stores();
+        let Some(text) = &stores.text else {
+            return Ok(());
+        };
+        let db = &stores.db;
+        let words: Vec<Vec<u8>> = text.iter().flatten().map(|(word, _)| word).collect();
+        for word in words {
+            text.remove(&word)?;
+        }
+        for (key, doc_data) in db.iter().flatten() {
+            if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
+                let key = String::from_utf8_lossy(&key);
+                for word in document_words(&doc).keys() {
+                    add_index_key(text, word, &key)?;
+                }
+            }
+        }
+        text.flush()
+    }
+
+    /// Retrieves a document by key.
+    pub fn get(&self, key: &str) -> Option<Document> {
+        self.get_result(key).ok().flatten()
+    }
+
+    /// Retrieves several documents in the order of `keys`, with `None` for
+    /// each key holding no readable document. Writes wait until every key is
+    /// read, so the documents are all from one moment. An expired document
+    /// is returned as `None` and left for `get` or `purge_expired` to delete.
+    pub fn get_many(&self, keys: &[&str]) -> Vec<Option<Document>> {
+        let _writer = lock(&self.writer);
+        let mut cache = self.cache.as_ref().map(lock);
+        keys.iter()
+            .map(|key| {
+                if self.is_expired(key.as_bytes()) {
+                    return None;
+                }
+                if let Some(doc) = cache.as_mut().and_then(|cache| cache.get(key)) {
+                    return Some(doc);
+                }
+                let doc_data = self.stores().db.get(key.as_bytes()).ok()??;
+                let doc: Document = serde_json::from_slice(&doc_data).ok()?;
+                if let Some(cache) = cache.as_mut() {
+                    cache.put(key, doc.clone());
+                }
+                Some(doc)
+            })
+            .collect()
+    }
+
+    /// Returns true if a document is stored under `key`, without reading or
+    /// decoding it. An expired document counts as absent.
+    pub fn contains_key(&self, key: &str) -> bool {
+        matches!(self.stores().db.contains_key(key.as_bytes()), Ok(true)) && !self.is_expired(key.as_bytes())
+    }
+
+    /// Retrieves a document by key, serving it from the cache when possible.
+    /// Returns `Ok(None)` if no document is stored under the key and
+    /// `NeemoError::Corrupt` if the stored bytes are not a valid document.
+    pub fn get_result(&self, key: &str) -> Result<Option<Document>, NeemoError> {
+        if self.is_expired(key.as_bytes()) {
+            if !self.read_only {
+                self.delete(key)?;
+            }
+            return Ok(None);
+        }
+        let epoch = match &self.cache {
+            Some(cache) => {
+                let mut cache = lock(cache);
+                if let Some(doc) = cache.get(key) {
+                    return Ok(Some(doc));
+                }
+                cache.epoch
+            }
+            None => 0,
+        };
+        let Some(doc_data) = self.stores().db.get(key.as_bytes())? else {
+            return Ok(None);
+        };
+        let doc: Document = serde_json::from_slice(&doc_data).map_err(|_| NeemoError::Corrupt(key.to_string()))?;
+        if let Some(cache) = &self.cache {
+            lock(cache).fill(key, doc.clone(), epoch);
+        }
+        Ok(Some(doc))
+    }
+
+    /// Retrieves a document like `get_result`, but logs a warning and
+    /// returns `Ok(None)` for a corrupt one, so reads spanning many documents
+    /// skip it instead of failing.
+    fn get_readable(&self, key: &str) -> Result<Option<Document>, NeemoError> {
+        match self.get_result(key) {
+            Err(NeemoError::Corrupt(key)) => {
+                warn!("Skipping corrupt document '{}'", key);
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Deletes a document by key.
+    ///
+    /// Only this document's key is removed from each index entry, so other
+    /// documents sharing a field value stay queryable.
+    pub fn delete(&self, key: &str) -> Result<(), NeemoError> {
+        let started = Instant::now();
+        let _writer = self.write_lock()?;
+        let count = usize::from(self.remove_document(key)?);
+        debug!("op=delete key={:?} count={} elapsed_us={}", key, count, started.elapsed().as_micros());
+        Ok(())
+    }
+
+    /// Deletes the documents under `keys` with their index entries while
+    /// other writes wait, returning how many were stored. Every document is
+    /// decoded before any is removed, so a corrupt one fails the call with
+    /// `NeemoError::Corrupt` and leaves all of them in place. An expired
+    /// document is removed but not counted.
+    pub fn delete_many(&self, keys: &[&str]) -> Result<usize, NeemoError> {
+        let _writer = self.write_lock()?;
+        for key in keys {
+            if let Some(doc_data) = self.stores().db.get(key.as_bytes())? {
+                serde_json::from_slice::<Document>(&doc_data).map_err(|_| NeemoError::Corrupt(key.to_string()))?;
+            }
+        }
+        let mut removed = 0;
+        for key in keys {
+            let live = !self.is_expired(key.as_bytes());
+            if self.remove_document(key)? && live {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Deletes every document along with its index and text index entries,
+    /// expiry times, blobs and history, then flushes. Index definitions and
+    /// settings are kept, and collections are left as they are.
+    pub fn clear(&self) -> Result<(), NeemoError> {
+        {
+            let _writer = self.write_lock()?;
+            let stores = self.stores();
+            stores.db.clear()?;
+            stores.index.clear()?;
+            save_index_definitions(&stores.index, &lock(&self.indexes))?;
+            for store in stores.side_stores() {
+                store.clear()?;
+            }
+            self.clear_cache();
+        }
+        self.flush()
+    }
+
+    /// Deletes a document with its index entries, blobs and expiry time
+    /// while the write lock is held. Returns whether there was a document.
+    fn remove_document(&self, key: &str) -> Result<bool, NeemoError> {
+        let stores = self.stores();
+        let removed = stores.db.remove(key.as_bytes())?;
+        self.invalidate_cached(key);
+        let found = removed.is_some();
+        if let Some(doc_data) = removed {
+            let doc: Document = serde_json::from_slice(&doc_data).map_err(|_| NeemoError::Corrupt(key.to_string()))?;
+            self.unindex_document(&stores.index, key, &doc)?;
+        }
+        self.take_blobs(key)?;
+        self.clear_expiry(key)?;
+        Ok(found)
+    }
+
+    /// Takes the write lock, failing if the database is open read-only.
+    fn write_lock(&self) -> Result<MutexGuard<'_, ()>, NeemoError> {
+        if self.read_only {
+            return Err(NeemoError::ReadOnly);
+        }
+        Ok(lock(&self.writer))
+    }
+
+    /// Removes a deleted document's key from its index and text index entries.
+    fn unindex_document(&self, index: &S, key: &str, doc: &Document) -> KvResult<()> {
+        for index_key in self.document_index_keys(doc) {
+            remove_index_key(index, &index_key, key)?;
+        }
+        self.unindex_text(key, doc)
+    }
+
+    /// Exchanges the documents stored under two keys, moving their index
+    /// entries along with them. Other writes wait for the whole swap. Each
+    /// document must satisfy the schemas of the key it moves to.
+    pub fn swap(&self, key_a: &str, key_b: &str) -> Result<(), NeemoError> {
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        let data_a = db.get(key_a.as_bytes())?.ok_or_else(|| NeemoError::NotFound(key_a.to_string()))?;
+        let data_b = db.get(key_b.as_bytes())?.ok_or_else(|| NeemoError::NotFound(key_b.to_string()))?;
+        let doc_a: Document = serde_json::from_slice(&data_a).map_err(|_| NeemoError::Corrupt(key_a.to_string()))?;
+        let doc_b: Document = serde_json::from_slice(&data_b).map_err(|_| NeemoError::Corrupt(key_b.to_string()))?;
+        self.validate(key_a, &doc_b)?;
+        self.validate(key_b, &doc_a)?;
+
+        for index_key in self.document_index_keys(&doc_a) {
+            remove_index_key(index, &index_key, key_a)?;
+        }
+        for index_key in self.document_index_keys(&doc_b) {
+            remove_index_key(index, &index_key, key_b)?;
+        }
+        db.insert(key_a.as_bytes(), &data_b)?;
+        db.insert(key_b.as_bytes(), &data_a)?;
+        for index_key in self.document_index_keys(&doc_a) {
+            add_index_key(index, &index_key, key_b)?;
+        }
+        for index_key in self.document_index_keys(&doc_b) {
+            add_index_key(index, &index_key, key_a)?;
+        }
+        self.unindex_text(key_a, &doc_a)?;
+        self.unindex_text(key_b, &doc_b)?;
+        self.index_text(key_b, &doc_a)?;
+        self.index_text(key_a, &doc_b)?;
+        if let Some(expiry) = &stores.expiry {
+            let (expires_a, expires_b) = (expiry.get(key_a.as_bytes())?, expiry.get(key_b.as_bytes())?);
+            for (key, expires_at) in [(key_a, expires_b), (key_b, expires_a)] {
+                match expires_at {
+                    Some(expires_at) => expiry.insert(key.as_bytes(), &expires_at)?,
+                    None => expiry.remove(key.as_bytes())?,
+                };
+            }
+        }
+        let (blobs_a, blobs_b) = (self.take_blobs(key_a)?, self.take_blobs(key_b)?);
+        if let Some(blobs) = &stores.blobs {
+            for (key, moved) in [(key_b, blobs_a), (key_a, blobs_b)] {
+                for (field, bytes) in moved {
+                    blobs.insert(&[blob_key(key, ""), field].concat(), &bytes)?;
+                }
+            }
+        }
+
+        self.invalidate_cached(key_a);
+        self.invalidate_cached(key_b);
+        Ok(())
+    }
+
+    /// Queries documents based on a field-value pair.
+    ///
+    /// A field with no index entries is answered by scanning every document;
+    /// the first such query logs a warning and the field is reported by
+    /// `unindexed_query_fields`.
+    pub fn query(&self, field: &str, value: Value) -> Result<Vec<Document>, NeemoError> {
+        Ok(self.query_with_keys(field, value)?.into_iter().map(|(_, doc)| doc).collect())
+    }
+
+    /// Queries like `query`, returning each match with the key it is stored
+    /// under.
+    pub fn query_with_keys(&self, field: &str, value: Value) -> Result<Vec<(String, Document)>, NeemoError> {
+        Ok(self.query_detailed(field, value)?.documents)
+    }
+
+    /// Queries like `query`, also returning each match's key and how many
+    /// indexed keys or documents were examined to find them.
+    pub fn query_detailed(&self, field: &str, value: Value) -> Result<QueryResult, NeemoError> {
+        let started = Instant::now();
+        let (documents, scanned) = match self.indexed_keys(field, &value)? {
+            None => self.scan_query(field, &value)?,
+            Some(keys) => {
+                // Capped keys only hold a hash of the value, and a write may land
+                // between reading the index and the document, so confirm the match.
+                let mut documents = Vec::new();
+                let mut scanned = 0;
+
+                for key in keys {
+                    scanned += 1;
+                    if let Some(doc) = self.get_readable(&key)? {
+                        if doc.get_path(field) == Some(&value) {
+                            documents.push((key, doc));
+                        }
+                    }
+                }
+                (documents, scanned)
+            }
+        };
+        let elapsed = started.elapsed();
+        debug!(
+            "op=query field={:?} count={} scanned={} elapsed_us={}",
+            field, documents.len(), scanned, elapsed.as_micros()
+        );
+        Ok(QueryResult { matched: documents.len(), documents, scanned, elapsed })
+    }
+
+    /// Queries like `query`, returning only the `project` fields of each
+    /// match, as in `Document::project`.
+    pub fn query_projected(&self, field: &str, value: Value, project: &[&str]) -> Result<Vec<Document>, NeemoError> {
+        Ok(self.query(field, value)?.iter().map(|doc| doc.project(project)).collect())
+    }
+
+    /// Queries like `query`, skipping the first `offset` matches and
+    /// returning at most `limit`. Documents past the page are not read.
+    pub fn query_paged(&self, field: &str, value: Value, offset: usize, limit: usize) -> Result<Vec<Document>, NeemoError> {
+        let Some(keys) = self.indexed_keys(field, &value)? else {
+            return self.readable_documents()
+                .map(|item| item.map(|(_, doc)| doc))
+                .filter(|item| !matches!(item, Ok(doc) if doc.get_path(field) != Some(&value)))
+                .skip(offset)
+                .take(limit)
+                .collect();
+        };
+        keys.iter()
+            .filter_map(|key| self.get_readable(key).transpose())
+            .filter(|item| !matches!(item, Ok(doc) if doc.get_path(field) != Some(&value)))
+            .skip(offset)
+            .take(limit)
+            .collect()
+    }
+
+    /// Returns the document keys indexed under a field value, or `None` if
+    /// the field has no index entries and must be scanned. The first lookup
+    /// of such a field logs a warning.
+    fn indexed_keys(&self, field: &str, value: &Value) -> Result<Option<Vec<String>>, NeemoError> {
+        if !self.is_indexed(field) {
+            if lock(&self.unindexed_fields).insert(field.to_string()) {
+                warn!("Query on unindexed field '{}' requires a full scan; create an index to avoid it", field);
+            }
+            return Ok(None);
+        }
+        let entry = self.stores().index.get(self.index_key(field, value).as_bytes())?;
+        Ok(Some(entry.map(|keys| decode_index_keys(&keys)).unwrap_or_default()))
+    }
+
+    /// Starts a query combining several conditions with sorting and paging.
+    pub fn query_builder(&self) -> QueryBuilder<'_, S> {
+        QueryBuilder { neemo: self, conditions: Vec::new(), sort_keys: Vec::new(), offset: 0, limit: None }
+    }
+
+    /// Describes how `query(field, value)` would run without running it:
+    /// whether it reads the field's index or scans, and how many documents
+    /// it would read. Nothing is logged for an unindexed field.
+    pub fn explain_query(&self, field: &str, value: Value) -> QueryPlan {
+        let indexed = self.is_indexed(field);
+        let candidates = if indexed {
+            match self.stores().index.get(self.index_key(field, &value).as_bytes()) {
+                Ok(Some(keys)) => decode_index_keys(&keys).len(),
+                _ => 0,
+            }
+        } else {
+            self.stores().db.len().unwrap_or(0)
+        };
+        let access = if indexed { AccessMethod::IndexLookup } else { AccessMethod::FullScan };
+        QueryPlan { field: field.to_string(), indexed, candidates, access }
+    }
+
+    /// Returns the documents matching every `(field, value)` condition, in
+    /// key order. The index key sets of the conditions are intersected so
+    /// only documents in every set are read; if no condition's field is
+    /// indexed, every document is scanned.
+    pub fn query_all(&self, conditions: &[(&str, Value)]) -> Result<Vec<Document>, NeemoError> {
+        let mut candidates: Option<BTreeSet<String>> = None;
+        for (field, value) in conditions {
+            if let Some(keys) = self.indexed_keys(field, value)? {
+                let keys: BTreeSet<String> = keys.into_iter().collect();
+                candidates = Some(match candidates {
+                    Some(current) => current.intersection(&keys).cloned().collect(),
+                    None => keys,
+                });
+            }
+        }
+
+        // Unindexed and capped conditions are only checked here.
+        let matches = |doc: &Document| conditions.iter().all(|(field, value)| doc.get_path(field) == Some(value));
+        match candidates {
+            Some(keys) => keys.iter()
+                .filter_map(|key| self.get_readable(key).transpose())
+                .filter(|item| !matches!(item, Ok(doc) if !matches(doc)))
+                .collect(),
+            None => self.readable_documents()
+                .map(|item| item.map(|(_, doc)| doc))
+                .filter(|item| !matches!(item, Ok(doc) if !matches(doc)))
+                .collect(),
+        }
+    }
+
+    /// Returns the documents matching any `(field, value)` condition, in key
+    /// order. Each condition is answered like `query`, and a document matching
+    /// several conditions is returned once.
+    pub fn query_any(&self, conditions: &[(&str, Value)]) -> Result<Vec<Document>, NeemoError> {
+        let mut matches = BTreeMap::new();
+        for (field, value) in conditions {
+            matches.extend(self.query_detailed(field, value.clone())?.documents);
+        }
+        Ok(matches.into_values().collect())
+    }
+
+    /// Returns the documents among `keys` whose `field` equals `value`, in
+    /// the order given. Only those keys are read.
+    pub fn query_within(&self, keys: &[&str], field: &str, value: Value) -> Vec<(String, Document)> {
+        keys.iter()
+            .filter_map(|key| self.get(key).map(|doc| (key.to_string(), doc)))
+            .filter(|(_, doc)| doc.get_path(field) == Some(&value))
+            .collect()
+    }
+
+    /// Returns true if any unexpired document holds `value` in `field`. For
+    /// an indexed field only the index is consulted, so no document is read.
+    pub fn exists_value(&self, field: &str, value: Value) -> bool {
+        if !self.is_indexed(field) {
+            return self.scan_query(field, &value).is_ok_and(|(documents, _)| !documents.is_empty());
+        }
+        match self.stores().index.get(self.index_key(field, &value).as_bytes()) {
+            Ok(Some(keys)) => decode_index_keys(&keys).iter().any(|key| !self.is_expired(key.as_bytes())),
+            _ => false,
+        }
+    }
+
+    /// Queries like `query`, then orders the matches by each `(field, desc)`
+    /// sort key in turn. The sort is stable, and documents whose sort field
+    /// is `null` or missing are placed by the null order in either direction.
+    pub fn query_sorted_multi(&self, field: &str, value: Value, sort_keys: &[(String, bool)]) -> Result<Vec<Document>, NeemoError> {
+        let nulls = *lock(&self.null_order);
+        let mut results = self.query(field, value)?;
+        results.sort_by(|a, b| {
+            sort_keys.iter()
+                .map(|(key, desc)| compare_fields(a.get_path(key), b.get_path(key), *desc, nulls))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(results)
+    }
+
+    /// Queries like `query`, ordering the matches by `sort_field`. Numbers
+    /// compare by value and strings alphabetically; documents whose sort
+    /// field is `null` or missing are placed by the null order.
+    pub fn query_sorted(&self, field: &str, value: Value, sort_field: &str, ascending: bool) -> Result<Vec<Document>, NeemoError> {
+        self.query_sorted_multi(field, value, &[(sort_field.to_string(), !ascending)])
+    }
+
+    /// Lists all documents ordered by `field`, which is compared as in
+    /// `query_sorted`.
+    pub fn list_sorted(&self, field: &str, ascending: bool) -> Vec<Document> {
+        let nulls = *lock(&self.null_order);
+        let mut documents = self.list();
+        documents.sort_by(|a, b| compare_fields(a.get_path(field), b.get_path(field), !ascending, nulls));
+        documents
+    }
+
+    /// Returns the fields that have been queried without an index.
+    pub fn unindexed_query_fields(&self) -> Vec<String> {
+        lock(&self.unindexed_fields).iter().cloned().collect()
+    }
+
+    /// Returns true if `field` has an index.
+    fn is_indexed(&self, field: &str) -> bool {
+        lock(&self.indexes).contains(field)
+    }
+
+    /// Indexes `field`, a top-level field or dotted path, adding entries for
+    /// every stored document. Later writes keep the entries up to date and
+    /// queries on the field read them instead of scanning.
+    pub fn create_index(&self, field: &str) -> Result<(), NeemoError> {
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        if lock(&self.indexes).contains(field) {
+            return Ok(());
+        }
+
+        let mut entries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (key, doc_data) in db.iter().flatten() {
+            if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
+                let key = String::from_utf8_lossy(&key).into_owned();
+                for (_, value) in index_fields(&doc).into_iter().filter(|(path, _)| path == field) {
+                    for index_key in self.value_index_keys(field, value) {
+                        entries.entry(index_key).or_default().push(key.clone());
+                    }
+                }
+            }
+        }
+        for (index_key, keys) in &entries {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            add_index_keys(index, index_key, &keys)?;
+        }
+
+        let mut indexes = lock(&self.indexes);
+        indexes.insert(field.to_string());
+        save_index_definitions(index, &indexes)
+    }
+
+    /// Stops indexing `field` and removes its entries. Queries on it then
+    /// scan every document.
+    pub fn drop_index(&self, field: &str) -> Result<(), NeemoError> {
+        let _writer = self.write_lock()?;
+        let index = &self.stores().index;
+        let mut indexes = lock(&self.indexes);
+        if !indexes.remove(field) {
+            return Ok(());
+        }
+        let index_keys: Vec<Vec<u8>> = [field_prefix(field), element_prefix(field)].iter()
+            .flat_map(|prefix| index.scan_prefix(prefix.as_bytes()).flatten().map(|(key, _)| key))
+            .collect();
+        for index_key in index_keys {
+            index.remove(&index_key)?;
+        }
+        save_index_definitions(index, &indexes)
+    }
+
+    /// Regenerates every index and text index entry from the stored
+    /// documents, replacing whatever the indexes held, to repair indexes that
+    /// have drifted from the documents. The indexed fields stay the same.
+    pub fn rebuild_index(&self) -> Result<(), NeemoError> {
+        {
+            let _writer = self.write_lock()?;
+            let stores = self.stores();
+            let (db, index) = (&stores.db, &stores.index);
+            let mut entries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for item in db.iter() {
+                let (key, doc_data) = item?;
+                let key = String::from_utf8_lossy(&key).into_owned();
+                let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) else {
+                    warn!("Skipping corrupt document '{}'", key);
+                    continue;
+                };
+                for index_key in self.document_index_keys(&doc) {
+                    entries.entry(index_key).or_default().push(key.clone());
+                }
+            }
+
+            let stale: Vec<Vec<u8>> = index.iter()
+                .map(|entry| entry.map(|(index_key, _)| index_key))
+                .filter(|index_key| !matches!(index_key, Ok(index_key) if index_key == INDEX_DEFINITIONS_KEY))
+                .collect::<Result<_, _>>()?;
+            for index_key in stale {
+                index.remove(&index_key)?;
+            }
+            for (index_key, keys) in &entries {
+                index.insert(index_key.as_bytes(), &serde_json::to_vec(keys)?)?;
+            }
+            index.flush()?;
+        }
+        self.rebuild_text_index()?;
+        Ok(())
+    }
+
+    /// Removes index references to documents that no longer exist, as left
+    /// behind by a write interrupted between the document and its index
+    /// entries. Returns the number of references removed.
+    pub fn compact_index(&self) -> Result<usize, NeemoError> {
+        let _writer = self.write_lock()?;
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        let mut removed = 0;
+        for entry in index.iter() {
+            let (index_key, keys) = entry?;
+            if index_key == INDEX_DEFINITIONS_KEY {
+                continue;
+            }
+            let keys = decode_index_keys(&keys);
+            let mut live = Vec::with_capacity(keys.len());
+            for key in &keys {
+                if db.contains_key(key.as_bytes())? {
+                    live.push(key.as_str());
+                }
+            }
+            if live.len() == keys.len() {
+                continue;
+            }
+            removed += keys.len() - live.len();
+            if live.is_empty() {
+                index.remove(&index_key)?;
+            } else {
+                index.insert(&index_key, &serde_json::to_vec(&live)?)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Returns the indexed fields in name order.
+    pub fn list_indexes(&self) -> Vec<String> {
+        lock(&self.indexes).iter().cloned().collect()
+    }
+
+    /// Lists the index keys a document is stored under, one for the value of
+    /// each indexed field and one for each distinct element of an indexed
+    /// array.
+    fn document_index_keys(&self, doc: &Document) -> BTreeSet<String> {
+        let indexes = lock(&self.indexes);
+        index_fields(doc).into_iter()
+            .filter(|(field, _)| indexes.contains(field))
+            .flat_map(|(field, value)| self.value_index_keys(&field, value))
+            .collect()
+    }
+
+    /// Lists the index keys of one indexed field value: the value's own key,
+    /// plus a key per distinct element if the value is an array.
+    fn value_index_keys(&self, field: &str, value: &Value) -> BTreeSet<String> {
+        let mut index_keys = BTreeSet::from([self.index_key(field, value)]);
+        if let Value::Array(elements) = value {
+            index_keys.extend(elements.iter().map(|element| self.element_key(field, element)));
+        }
+        index_keys
+    }
+
+    /// Finds documents whose `field` equals `value` by scanning every
+    /// document, returning the matches and the number of documents scanned.
+    fn scan_query(&self, field: &str, value: &Value) -> Result<(Vec<(String, Document)>, usize), NeemoError> {
+        let mut scanned = 0;
+        let documents = self.readable_documents()
+            .inspect(|_| scanned += 1)
+            .filter(|item| !matches!(item, Ok((_, doc)) if doc.get_path(field) != Some(value)))
+            .collect::<Result<_, _>>()?;
+        Ok((documents, scanned))
+    }
+
+    /// Lists all documents, leaving out expired ones.
+    pub fn list(&self) -> Vec<Document> {
+        self.list_with_keys().into_iter().map(|(_, doc)| doc).collect()
+    }
+
+    /// Lists all documents like `list`, keeping only the `project` fields
+    /// of each, as in `Document::project`.
+    pub fn list_projected(&self, project: &[&str]) -> Vec<Document> {
+        self.iter_documents().filter_map(Result::ok).map(|(_, doc)| doc.project(project)).collect()
+    }
+
+    /// Lists all documents with the keys they are stored under, in key order.
+    pub fn list_with_keys(&self) -> Vec<(String, Document)> {
+        self.readable_documents().filter_map(Result::ok).collect()
+    }
+
+    /// Iterates the documents with their keys in key order, leaving out
+    /// expired ones. Each document is read and decoded only when the
+    /// iterator reaches it, so callers can stop early. A stored value that is
+    /// not a valid document yields `NeemoError::Corrupt`.
+    pub fn iter_documents(&self) -> impl Iterator<Item = Result<(String, Document), NeemoError>> + '_ {
+        self.decode_entries(self.stores().db.iter())
+    }
+
+    /// Decodes entries of the document store like `iter_documents`, leaving
+    /// out expired ones.
+    fn decode_entries<'a>(&'a self, entries: KvIter<'a>) -> impl Iterator<Item = Result<(String, Document), NeemoError>> + 'a {
+        entries
+            .filter(|item| !matches!(item, Ok((key, _)) if self.is_expired(key)))
+            .map(|item| {
+                let (key, value) = item?;
+                let key = String::from_utf8_lossy(&key).into_owned();
+                match serde_json::from_slice(&value) {
+                    Ok(doc) => Ok((key, doc)),
+                    Err(_) => Err(NeemoError::Corrupt(key)),
+                }
+            })
+    }
+
+    /// Iterates the documents like `iter_documents`, but logs a warning for
+    /// each corrupt one and leaves it out, so only storage failures remain as
+    /// errors.
+    fn readable_documents(&self) -> impl Iterator<Item = Result<(String, Document), NeemoError>> + '_ {
+        self.iter_documents().filter(is_readable)
+    }
+
+    /// Returns the documents whose key starts with `prefix`, with their
+    /// keys, in key order. Only those documents are read. Expired and
+    /// corrupt documents are left out.
+    pub fn get_prefix(&self, prefix: &str) -> Vec<(String, Document)> {
+        self.decode_entries(self.stores().db.scan_prefix(prefix.as_bytes()))
+            .filter(is_readable)
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    /// Returns the documents whose key lies in `start..end`, with their
+    /// keys, in key order. Keys compare bytewise, so `start` is included and
+    /// `end` is not, as with `range_query`; a range whose `end` is not past
+    /// `start` is empty. Expired and corrupt documents are left out.
+    pub fn scan_keys(&self, start: &str, end: &str) -> Vec<(String, Document)> {
+        if start >= end {
+            return Vec::new();
+        }
+        let range = self.stores().db.range(Bound::Included(start.as_bytes()), Bound::Excluded(end.as_bytes()));
+        self.decode_entries(range)
+            .filter(is_readable)
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    /// Returns the documents for which `predicate` holds, in key order.
+    /// Every document is read, so this suits conditions the index cannot
+    /// answer, such as comparisons or string prefixes.
+    pub fn filter<F>(&self, predicate: F) -> Vec<Document>
+    where
+        F: Fn(&Document) -> bool,
+    {
+        self.iter_documents()
+            .filter_map(Result::ok)
+            .map(|(_, doc)| doc)
+            .filter(|doc| predicate(doc))
+            .collect()
+    }
+
+    /// Lists at most `limit` documents after skipping the first `offset`.
+    /// Corrupt documents are skipped with a warning and not counted, and
+    /// documents past the page are not read.
+    pub fn list_paged(&self, offset: usize, limit: usize) -> Vec<Document> {
+        self.readable_documents()
+            .filter_map(Result::ok)
+            .map(|(_, doc)| doc)
+            .skip(offset)
+            .take(limit)
+            .collect()
+    }
+
+    /// Iterates every key with its stored bytes, without decoding them, so
+    /// tooling can inspect records that are not valid documents. Entries are
+    /// read as the iterator advances.
+    pub fn raw_iter(&self) -> impl Iterator<Item = Result<(String, Vec<u8>), NeemoError>> + '_ {
+        self.stores().db.iter().map(|item| item.map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)))
+    }
+
+    /// Counts the stored documents without decoding them, leaving out
+    /// expired ones as `list` does.
+    pub fn count(&self) -> Result<usize, NeemoError> {
+        let stored = self.stores().db.len()?;
+        let Some(expiry) = &self.stores().expiry else {
+            return Ok(stored);
+        };
+        let now = now_millis();
+        let mut expired = 0;
+        for item in expiry.iter() {
+            let (key, expires_at) = item?;
+            if decode_expiry(&expires_at) <= now && self.stores().db.contains_key(&key)? {
+                expired += 1;
+            }
+        }
+        Ok(stored.saturating_sub(expired))
+    }
+
+    /// Counts the unexpired documents matching a field-value pair from the
+    /// index alone. An unindexed field is counted by scanning like `query`.
+    pub fn count_query(&self, field: &str, value: Value) -> Result<usize, NeemoError> {
+        match self.indexed_keys(field, &value)? {
+            Some(keys) => Ok(keys.iter().filter(|key| !self.is_expired(key.as_bytes())).count()),
+            None => Ok(self.scan_query(field, &value)?.0.len()),
+        }
+    }
+
+    /// Counts the unexpired documents whose key starts with `prefix`.
+    pub fn count_by_prefix(&self, prefix: &str) -> usize {
+        self.stores().db.scan_prefix(prefix.as_bytes()).flatten().filter(|(key, _)| !self.is_expired(key)).count()
+    }
+
+    /// Groups the keys of unexpired documents under `prefix` by their next
+    /// `separator`-delimited segment, returning each segment with its key
+    /// count in key order.
+    pub fn child_prefixes(&self, prefix: &str, separator: char) -> Vec<(String, usize)> {
+        let mut children: BTreeMap<String, usize> = BTreeMap::new();
+
+        for (key, _) in self.stores().db.scan_prefix(prefix.as_bytes()).flatten().filter(|(key, _)| !self.is_expired(key)) {
+            let rest = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            let segment = match rest.split_once(separator) {
+                Some((segment, _)) => segment.to_string(),
+                None => rest,
+            };
+            *children.entry(segment).or_insert(0) += 1;
+        }
+        children.into_iter().collect()
+    }
+
+    /// Supports transactions.
+    ///
+    /// `f` reads and writes documents through a [`Transaction`]. Its writes
+    /// are only applied if it returns `Ok`, and then the documents and their
+    /// index entries are committed together, so an error or panic leaves
+    /// the database untouched. Otherwise the writes behave as `insert` and
+    /// `delete` do, keeping the text index, history and expiry times up to
+    /// date. Other writes wait until the commit is done.
+    pub fn transaction<F, T>(&self, f: F) -> Result<T, NeemoError>
+    where
+        F: FnOnce(&Transaction<'_, S>) -> Result<T, NeemoError>,
+    {
+        let _writer = self.write_lock()?;
+        let transaction = Transaction { neemo: self, writes: RefCell::new(BTreeMap::new()) };
+        let result = f(&transaction)?;
+        self.commit_documents(&transaction.writes.into_inner())?;
+        Ok(result)
+    }
+
+    /// Stores `docs`, deleting the keys whose document is `None`, with the
+    /// documents and index entries committed together. The text index,
+    /// history, expiry times and blobs are then updated as by `insert` and
+    /// `delete`. The documents must already be validated, and the write lock
+    /// held.
+    fn commit_documents(&self, docs: &BTreeMap<String, Option<Document>>) -> Result<(), NeemoError> {
+        let stores = self.stores();
+        let (db, index) = (&stores.db, &stores.index);
+        let (db_writes, index_writes) = (StagedStore::new(db), StagedStore::new(index));
+        let mut replaced = Vec::with_capacity(docs.len());
+        for (key, doc) in docs {
+            let old_data = match doc {
+                Some(doc) => db_writes.write_entry(key.as_bytes(), &serde_json::to_vec(doc)?)?,
+                None => db_writes.remove_entry(key.as_bytes())?,
+            };
+            let old = old_data.as_deref().and_then(|data| serde_json::from_slice::<Document>(data).ok());
+            let old_keys = old.as_ref().map(|old| self.document_index_keys(old)).unwrap_or_default();
+            let new_keys = doc.as_ref().map(|doc| self.document_index_keys(doc)).unwrap_or_default();
+            for index_key in old_keys.difference(&new_keys) {
+                remove_index_key(&index_writes, index_key, key)?;
+            }
+            for index_key in new_keys.difference(&old_keys) {
+                add_index_key(&index_writes, index_key, key)?;
+            }
+            replaced.push((old_data, old));
+        }
+        commit_together(db, db_writes.into_writes(), index, index_writes.into_writes())?;
+
+        for ((key, doc), (old_data, old)) in docs.iter().zip(replaced) {
+            match doc {
+                Some(doc) => {
+                    if let Some(old_data) = &old_data {
+                        self.archive(key, old_data)?;
+                    }
+                    if let Some(old) = &old {
+                        self.remove_stale_words(key, old, doc)?;
+                    }
+                    self.add_new_words(key, old.as_ref(), doc)?;
+                }
+                None => {
+                    if let Some(old) = &old {
+                        self.unindex_text(key, old)?;
+                    }
+                    self.take_blobs(key)?;
+                }
+            }
+            self.clear_expiry(key)?;
+            self.invalidate_cached(key);
+        }
+        Ok(())
+    }
+
+    /// Supports range queries.
+    ///
+    /// Returns documents whose `field` lies in `start..end`, so a document
+    /// equal to `end` is left out; `range_query_with` can include it. A `null`
+    /// bound leaves that side open, matching every value of the other bound's
+    /// type. Documents whose field is `null` or missing are only returned by
+    /// an open start under `NullOrder::First` (ahead of the values) or an open
+    /// end under `NullOrder::Last` (after them).
+    pub fn range_query(&self, field: &str, start: Value, end: Value) -> Result<Vec<Document>, NeemoError> {
+        self.range_query_with(field, start, end, RangeEnd::Excluded)
+    }
+
+    /// Queries a range like `range_query`, with `end_mode` deciding whether
+    /// documents equal to `end` are returned.
+    pub fn range_query_with(&self, field: &str, start: Value, end: Value, end_mode: RangeEnd) -> Result<Vec<Document>, NeemoError> {
+        if start.is_null() || end.is_null() || !self.is_indexed(field) {
+            return self.scan_range_query(field, &start, &end, end_mode);
+        }
+
+        let start_key = self.range_bound_key(field, &start);
+        let mut end_key = self.range_bound_key(field, &end);
+        let mut results = Vec::new();
+
+        let end_bound = match end_mode {
+            RangeEnd::Excluded => Bound::Excluded(end_key.as_bytes()),
+            RangeEnd::Included => {
+                // Spellings of a number follow its sortable prefix and never
+                // contain `~`, so this bound takes in all of them.
+                if end.is_number() {
+                    end_key.push('~');
+                }
+                Bound::Included(end_key.as_bytes())
+            }
+        };
+        let (start, end) = (Bound::Included(start_key.as_bytes()), end_bound);
+        for entry in self.stores().index.range(start, end) {
+            for key in decode_index_keys(&entry?.1) {
+                results.extend(self.get_readable(&key)?);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Scans every document for a range, as needed for an open (`null`)
+    /// bound or an unindexed field.
+    fn scan_range_query(&self, field: &str, start: &Value, end: &Value, end_mode: RangeEnd) -> Result<Vec<Document>, NeemoError> {
+        let nulls = *lock(&self.null_order);
+        let mut values = Vec::new();
+        let mut missing = Vec::new();
+
+        for item in self.readable_documents() {
+            let (_, doc) = item?;
+            match doc.get_path(field) {
+                None | Some(Value::Null) => missing.push(doc),
+                Some(value) => {
+                    if in_range(value, start, end, end_mode) {
+                        values.push(doc);
+                    }
+                }
+            }
+        }
+
+        values.sort_by(|a, b| compare_fields(a.get_path(field), b.get_path(field), false, nulls));
+        missing.sort_by(|a, b| compare_fields(a.get_path(field), b.get_path(field), false, nulls));
+        Ok(match nulls {
+            NullOrder::First if start.is_null() => missing.into_iter().chain(values).collect(),
+            NullOrder::Last if end.is_null() => values.into_iter().chain(missing).collect(),
+            _ => values,
+        })
+    }
+
+    /// Returns documents whose `field` is strictly greater than `value` and
+    /// of the same type, in ascending order of the field. An indexed numeric
+    /// field is answered from the index alone.
+    pub fn query_gt(&self, field: &str, value: Value) -> Vec<Document> {
+        self.compare_query(field, &value, Ordering::Greater)
+    }
+
+    /// Returns documents whose `field` is strictly less than `value` and of
+    /// the same type, in ascending order of the field. An indexed numeric
+    /// field is answered from the index alone.
+    pub fn query_lt(&self, field: &str, value: Value) -> Vec<Document> {
+        self.compare_query(field, &value, Ordering::Less)
+    }
+
+    /// Returns documents whose array `field` holds `element`, in key order.
+    /// An indexed field is answered from its per-element entries; arrays
+    /// indexed before those entries existed are found after `rebuild_index`.
+    pub fn query_contains(&self, field: &str, element: Value) -> Vec<Document> {
+        let holds = |doc: &Document| matches!(doc.get_path(field), Some(Value::Array(elements)) if elements.contains(&element));
+        if !self.is_indexed(field) {
+            return self.filter(holds);
+        }
+        let Ok(Some(keys)) = self.stores().index.get(self.element_key(field, &element).as_bytes()) else {
+            return Vec::new();
+        };
+        let mut keys = decode_index_keys(&keys);
+        keys.sort();
+        keys.iter()
+            .filter_map(|key| self.get(key))
+            .filter(|doc| holds(doc))
+            .collect()
+    }
+
+    /// Finds documents whose `field` compares to `value` as `wanted`.
+    fn compare_query(&self, field: &str, value: &Value, wanted: Ordering) -> Vec<Document> {
+        let matches = |doc: &Document| {
+            doc.get_path(field).is_some_and(|found| {
+                value_rank(found) == value_rank(value) && compare_values(found, value) == wanted
+            })
+        };
+        let boundary = match value {
+            Value::Number(n) if self.is_indexed(field) => format!("{}{}", field_prefix(field), sortable_number(n)),
+            _ => {
+                let mut results = self.filter(matches);
+                results.sort_by(|a, b| compare_fields(a.get_path(field), b.get_path(field), false, NullOrder::Last));
+                return results;
+            }
+        };
+
+        // Numeric keys share the `field:!` prefix and sort by value, with
+        // every spelling of a number after its sortable prefix.
+        let numbers = format!("{}!", field_prefix(field));
+        let past_boundary = format!("{}~", boundary);
+        let entries: Vec<Vec<u8>> = match wanted {
+            Ordering::Greater => self.stores().index.range(Bound::Excluded(past_boundary.as_bytes()), Bound::Unbounded)
+                .flatten()
+                .take_while(|(index_key, _)| index_key.starts_with(numbers.as_bytes()))
+                .map(|(_, keys)| keys)
+                .collect(),
+            _ => self.stores().index.range(Bound::Included(numbers.as_bytes()), Bound::Excluded(boundary.as_bytes()))
+                .flatten()
+                .map(|(_, keys)| keys)
+                .collect(),
+        };
+        entries.iter()
+            .flat_map(|keys| decode_index_keys(keys))
+            .filter_map(|key| self.get(&key))
+            .filter(|doc| matches(doc))
+            .collect()
+    }
+
+    /// Supports full-text search.
+    ///
+    /// Matching is case-insensitive and looks inside nested objects and arrays.
+    /// With a `limit`, at most that many documents are returned and `truncated`
+    /// reports whether further matches exist. When the database has a text
+    /// index, only documents containing every query word are read.
+    pub fn full_text_search(&self, query: &str, limit: Option<usize>) -> Result<SearchResults, NeemoError> {
+        self.full_text_search_with(query, limit, TextMatch::Substring)
+    }
+
+    /// Searches like `full_text_search`, matching the query as `mode` says.
+    pub fn full_text_search_with(&self, query: &str, limit: Option<usize>, mode: TextMatch) -> Result<SearchResults, NeemoError> {
+        let query = query.to_lowercase();
+        let query_words = words(&query);
+        let matches = |text: &str| match mode {
+            TextMatch::Substring => text.to_lowercase().contains(&query),
+            TextMatch::WholeWord => {
+                !query_words.is_empty() && words(text).windows(query_words.len()).any(|window| window == query_words)
+            }
+        };
+        let candidates: Box<dyn Iterator<Item = Result<Document, NeemoError>>> = match self.text_candidates(&query_words, mode)? {
+            Some(keys) => Box::new(keys.into_iter().filter_map(|key| self.get_readable(&key).transpose())),
+            None => Box::new(self.readable_documents().map(|item| item.map(|(_, doc)| doc))),
+        };
+        let mut documents = Vec::new();
+        let mut truncated = false;
+
+        for doc in candidates {
+            let doc = doc?;
+            if !doc.data.values().any(|value| value_contains_text(value, &matches)) {
+                continue;
+            }
+            if limit.is_some_and(|limit| documents.len() >= limit) {
+                truncated = true;
+                break;
+            }
+            documents.push(doc);
+        }
+        Ok(SearchResults { documents, truncated })
+    }
+
+    /// Returns the documents containing any of the query's words, most
+    /// relevant first. Each query word a document contains adds
+    /// `1 + ln(occurrences)` to its score, so holding more of the words
+    /// outweighs repeating one of them. Words are matched whole, and
+    /// documents with equal scores stay in key order.
+    pub fn full_text_search_ranked(&self, query: &str) -> Vec<(Document, f64)> {
+        let query_words: BTreeSet<String> = words(query).into_iter().collect();
+        let candidates: Box<dyn Iterator<Item = Document>> = match &self.stores().text {
+            Some(text) => {
+                let keys: BTreeSet<String> = query_words.iter()
+                    .filter_map(|word| text.get(word.as_bytes()).ok().flatten())
+                    .flat_map(|keys| decode_index_keys(&keys))
+                    .collect();
+                Box::new(keys.into_iter().filter_map(|key| self.get(&key)))
+            }
+            None => Box::new(self.list().into_iter()),
+        };
+
+        let mut ranked: Vec<(Document, f64)> = candidates
+            .filter_map(|doc| {
+                let counts = document_words(&doc);
+                let score: f64 = query_words.iter()
+                    .filter_map(|word| counts.get(word))
+                    .map(|&count| 1.0 + (count as f64).ln())
+                    .sum();
+                (score > 0.0).then_some((doc, score))
+            })
+            .collect();
+        ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        ranked
+    }
+
+    /// Returns the keys of the documents holding every query word, in key
+    /// order, or `None` if there is no text index or no word to look up.
+    /// For substring matches each query word may lie inside a longer
+    /// indexed word, so the matching words are found by scanning the text
+    /// index's words rather than the documents.
+    fn text_candidates(&self, query_words: &[String], mode: TextMatch) -> Result<Option<BTreeSet<String>>, NeemoError> {
+        let stores = self.stores();
+        let Some(text) = stores.text.as_ref().filter(|_| !query_words.is_empty()) else {
+            return Ok(None);
+        };
+        let mut candidates: Option<BTreeSet<String>> = None;
+        for query_word in query_words {
+            let mut keys = BTreeSet::new();
+            match mode {
+                TextMatch::WholeWord => {
+                    if let Some(entry) = text.get(query_word.as_bytes())? {
+                        keys.extend(decode_index_keys(&entry));
+                    }
+                }
+                TextMatch::Substring => {
+                    for entry in text.iter() {
+                        let (word, entry) = entry?;
+                        if String::from_utf8_lossy(&word).contains(query_word.as_str()) {
+                            keys.extend(decode_index_keys(&entry));
+                        }
+                    }
+                }
+            }
+            candidates = Some(match candidates {
+                Some(current) => current.intersection(&keys).cloned().collect(),
+                None => keys,
+            });
+        }
+        Ok(candidates)
+    }
+
+    /// Returns each distinct value of `field` with the number of unexpired
+    /// documents holding it, most common first and then by value. For an
+    /// indexed field only the index is scanned.
+    pub fn facet(&self, field: &str) -> Vec<(Value, usize)> {
+        let mut counts: Vec<(Value, usize)> = if self.is_indexed(field) {
+            let prefix = field_prefix(field);
+            self.stores().index.scan_prefix(prefix.as_bytes())
+                .flatten()
+                .filter_map(|(index_key, keys)| {
+                    let value = index_key_value(&index_key[prefix.len()..])?;
+                    let count = decode_index_keys(&keys).iter().filter(|key| !self.is_expired(key.as_bytes())).count();
+                    (count > 0).then_some((value, count))
+                })
+                .collect()
+        } else {
+            // Values are not hashable, so they are counted by their JSON text.
+            let mut counts: HashMap<String, (Value, usize)> = HashMap::new();
+            for (_, doc) in self.iter_documents().filter_map(Result::ok) {
+                if let Some(value) = doc.get_path(field) {
+                    counts.entry(value.to_string()).or_insert_with(|| (value.clone(), 0)).1 += 1;
+                }
+            }
+            counts.into_values().collect()
+        };
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| compare_values(a, b)));
+        counts
+    }
+
+    /// Returns each distinct value of `field` once, ordered by value as in
+    /// `list_sorted`. For an indexed field the values are read from the
+    /// index, and documents are only read for values too long to be kept
+    /// whole in their index key.
+    pub fn distinct(&self, field: &str) -> Vec<Value> {
+        let mut values: Vec<Value> = if self.is_indexed(field) {
+            let prefix = field_prefix(field);
+            self.stores().index.scan_prefix(prefix.as_bytes())
+                .flatten()
+                .filter_map(|(index_key, keys)| {
+                    index_key_value(&index_key[prefix.len()..]).or_else(|| {
+                        decode_index_keys(&keys).iter().find_map(|key| self.get(key)?.get_path(field).cloned())
+                    })
+                })
+                .collect()
+        } else {
+            let mut seen = HashSet::new();
+            self.iter_documents()
+                .filter_map(Result::ok)
+                .filter_map(|(_, doc)| doc.get_path(field).cloned())
+                .filter(|value| seen.insert(value.to_string()))
+                .collect()
+        };
+        values.sort_by(compare_values);
+        values
+    }
+
+    /// Supports aggregation queries.
+    ///
+    /// `op` is one of `sum`, `count`, `avg`, `min` or `max`, computed over
+    /// the numeric values of `field`. When no document holds a number in the
+    /// field, `count` is 0 and the other operations return `None`, as they
+    /// do for an unknown `op`.
+    pub fn aggregate(&self, field: &str, op: &str) -> Result<Option<Value>, NeemoError> {
+        self.aggregate_with_progress(field, op, 0, |_| true)
+    }
+
+    /// Aggregates like `aggregate`, calling `progress` with the number of
+    /// documents scanned so far after every `every` documents (never if
+    /// `every` is 0). Returning `false` from `progress` cancels the scan.
+    pub fn aggregate_with_progress<F>(&self, field: &str, op: &str, every: usize, mut progress: F) -> Result<Option<Value>, NeemoError>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut totals = Accumulator::default();
+
+        for (scanned, item) in self.stores().db.iter().enumerate() {
+            if every > 0 && scanned > 0 && scanned % every == 0 && !progress(scanned) {
+                return Err(NeemoError::Cancelled);
+            }
+            let (key, doc_data) = item?;
+            if self.is_expired(&key) {
+                continue;
+            }
+            match serde_json::from_slice::<Document>(&doc_data) {
+                Ok(doc) => totals.add(doc.get_path(field)),
+                Err(_) => warn!("Skipping corrupt document '{}'", String::from_utf8_lossy(&key)),
+            }
+        }
+        Ok(totals.result(op))
+    }
+
+    /// Aggregates `agg_field` like `aggregate`, separately for each value of
+    /// `group_field`. Groups are keyed by that value, with strings unquoted;
+    /// documents without it are skipped. A group with no numbers in
+    /// `agg_field` is only reported by `count`.
+    pub fn aggregate_grouped(&self, group_field: &str, agg_field: &str, op: &str) -> HashMap<String, Value> {
+        let mut groups: HashMap<String, Accumulator> = HashMap::new();
+
+        for (key, doc_data) in self.stores().db.iter().flatten() {
+            if self.is_expired(&key) {
+                continue;
+            }
+            if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
+                let group = match doc.get_path(group_field) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                    None => continue,
+                };
+                groups.entry(group).or_default().add(doc.get_path(agg_field));
+            }
+        }
+        groups.into_iter()
+            .filter_map(|(group, totals)| Some((group, totals.result(op)?)))
+            .collect()
+    }
+
+    /// Folds `f` over every unexpired document, starting from `init`.
+    /// Corrupt documents are skipped with a warning.
+    pub fn fold<A, F>(&self, init: A, f: F) -> A
+    where
+        F: Fn(A, &Document) -> A,
+    {
+        self.readable_documents()
+            .filter_map(Result::ok)
+            .fold(init, |acc, (_, doc)| f(acc, &doc))
+    }
+
+    /// Folds `f` over the documents matching a field-value pair.
+    pub fn fold_query<A, F>(&self, field: &str, value: Value, init: A, f: F) -> Result<A, NeemoError>
+    where
+        F: Fn(A, &Document) -> A,
+    {
+        Ok(self.query(field, value)?.iter().fold(init, f))
+    }
+
+    /// Returns the document and index entry counts and the on-disk size of
+    /// the document and index stores.
+    pub fn stats(&self) -> Result<NeemoStats, NeemoError> {
+        let stores = self.stores();
+        let definitions = usize::from(stores.index.contains_key(INDEX_DEFINITIONS_KEY)?);
+        Ok(NeemoStats {
+            documents: self.count()?,
+            index_entries: stores.index.len()? - definitions,
+            data_size: stores.db.size_on_disk()?,
+            index_size: stores.index.size_on_disk()?,
+        })
+    }
+
+    /// Returns the combined on-disk size of the document and index stores.
+    pub fn size_on_disk(&self) -> Result<u64, NeemoError> {
+        let stores = self.stores();
+        let mut size = stores.db.size_on_disk()? + stores.index.size_on_disk()?;
+        for store in stores.side_stores() {
+            size += store.size_on_disk()?;
+        }
+        Ok(size)
+    }
+
+    /// Writes every buffered change in every store to disk, returning once
+    /// it is durable. Use after writes that must survive a crash.
+    pub fn flush(&self) -> Result<(), NeemoError> {
+        let stores = self.stores();
+        stores.db.flush()?;
+        stores.index.flush()?;
+        for store in stores.side_stores() {
+            store.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes every store and closes the database. Unlike dropping the
+    /// handle, which flushes too but can only log a failure, this reports
+    /// whether everything reached disk.
+    pub fn close(self) -> Result<(), NeemoError> {
+        self.flush()
+    }
+
+    /// Applies `ops` in order while other writes wait, keeping indexes up
+    /// to date as `insert` and `delete` do. Every inserted document is
+    /// validated before anything is written, so one breaking a schema
+    /// leaves the database unchanged. The stores are flushed once at the end.
+    pub fn batch(&self, ops: &[BatchOp]) -> Result<(), NeemoError> {
+        for op in ops {
+            if let BatchOp::Insert { key, doc } = op {
+                self.validate(key, doc)?;
+            }
+        }
+        {
+            let _writer = self.write_lock()?;
+            let stores = self.stores();
+            let (db, index) = (&stores.db, &stores.index);
+            for op in ops {
+                match op {
+                    BatchOp::Insert { key, doc } => self.write_document(db, index, key, doc)?,
+                    BatchOp::Delete { key } => {
+                        self.remove_document(key)?;
+                    }
+                }
+            }
+        }
+        self.flush()
+    }
+
+    /// Returns the current database settings.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            id_field: lock(&self.id_field).clone(),
+            index_value_cap: *lock(&self.index_value_cap),
+            null_order: *lock(&self.null_order),
+            indexes: self.list_indexes(),
+            schemas: lock(&self.schemas).clone(),
+            history_limit: *lock(&self.history_limit),
+        }
+    }
+
+    /// Replaces the database settings with `meta`, creating any index it
+    /// defines. Existing indexes are kept.
+    pub fn apply_metadata(&self, meta: Metadata) -> Result<(), NeemoError> {
+        *lock(&self.id_field) = meta.id_field;
+        *lock(&self.index_value_cap) = meta.index_value_cap;
+        *lock(&self.null_order) = meta.null_order;
+        *lock(&self.schemas) = meta.schemas;
+        *lock(&self.history_limit) = meta.history_limit;
+        for field in &meta.indexes {
+            self.create_index(field)?;
+        }
+        Ok(())
+    }
+
+    /// Sets where `null` and missing fields go in sorts and open ranges.
+    pub fn set_null_order(&self, order: NullOrder) {
+        *lock(&self.null_order) = order;
+    }
+
+    /// Reads the document store in key order, `EXPORT_CHUNK_SIZE` entries
+    /// at a time. A backend that locks while reading is only locked for a
+    /// chunk, so other operations proceed between chunks; writes made
+    /// meanwhile may or may not be seen. A failed read ends the iteration
+    /// with its error.
+    fn document_chunks(&self) -> impl Iterator<Item = KvResult<Vec<(Vec<u8>, Vec<u8>)>>> + '_ {
+        let mut after: Option<Vec<u8>> = None;
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let start = after.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+            let chunk = self.stores().db.range(start, Bound::Unbounded)
+                .take(EXPORT_CHUNK_SIZE)
+                .collect::<KvResult<Vec<(Vec<u8>, Vec<u8>)>>>();
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    done = true;
+                    return Some(Err(e));
+                }
+            };
+            done = chunk.len() < EXPORT_CHUNK_SIZE;
+            after = chunk.last().map(|(key, _)| key.clone());
+            (!chunk.is_empty()).then_some(Ok(chunk))
+        })
+    }
+
+    /// Supports exporting data.
+    ///
+    /// Each line holds an `ExportRecord`, `{"key":...,"doc":...}`, so keys
+    /// survive a round trip through `import`. Settings are written to a
+    /// `<path>.meta.json` sidecar next to the documents. Documents are read
+    /// in chunks so other operations are not blocked for the whole export;
+    /// a document written while it runs may or may not be included.
+    pub fn export(&self, path: &str) -> Result<(), NeemoError> {
+        self.export_records(path, File::create(path)?)?;
+        Ok(())
+    }
+
+    /// Exports like `export`, compressing the document lines with gzip.
+    /// The settings sidecar is written uncompressed as before.
+    #[cfg(feature = "flate2")]
+    pub fn export_archive(&self, path: &str) -> Result<(), NeemoError> {
+        let encoder = GzEncoder::new(File::create(path)?, Compression::default());
+        self.export_records(path, encoder)?.finish()?;
+        Ok(())
+    }
+
+    /// Writes the settings sidecar for `path` and one `ExportRecord` line per
+    /// document to `out`, handing `out` back once everything is flushed.
+    fn export_records<W: Write>(&self, path: &str, out: W) -> Result<W, NeemoError> {
+        let meta_file = File::create(metadata_path(path))?;
+        serde_json::to_writer_pretty(meta_file, &self.metadata())?;
+
+        let mut writer = io::BufWriter::new(out);
+        for chunk in self.document_chunks() {
+            for (key, doc_data) in chunk? {
+                if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
+                    let record = ExportRecord { key: String::from_utf8_lossy(&key).into_owned(), doc };
+                    serde_json::to_writer(&mut writer, &record)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+        }
+        Ok(writer.into_inner().map_err(io::IntoInnerError::into_error)?)
+    }
+
+    /// Exports like `export`, writing each record as canonical JSON with
+    /// object keys sorted at every level. With `dedupe`, documents whose
+    /// canonical form was already written, under any key, are dropped.
+    /// Returns how many duplicates were dropped.
+    pub fn compact_export(&self, path: &str, dedupe: bool) -> Result<usize, NeemoError> {
+        let meta_file = File::create(metadata_path(path))?;
+        serde_json::to_writer_pretty(meta_file, &self.metadata())?;
+
+        let file = File::create(path)?;
+        let mut writer = io::BufWriter::new(file);
+        let mut written = HashSet::new();
+        let mut duplicates = 0;
+
+        for chunk in self.document_chunks() {
+            for (key, doc_data) in chunk? {
+                let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) else {
+                    continue;
+                };
+                let canonical = canonical_json(serde_json::to_value(&doc)?);
+                if dedupe && !written.insert(canonical.to_string()) {
+                    duplicates += 1;
+                    continue;
+                }
+                let record = canonical_json(serde_json::json!({ "key": String::from_utf8_lossy(&key), "doc": canonical }));
+                writer.write_all(record.to_string().as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        writer.flush()?;
+        Ok(duplicates)
+    }
+
+    /// Exports documents as CSV. The header row is `_key` followed by every
+    /// top-level field found in any document, in name order, and each
+    /// document fills one row. Fields a document lacks are left blank,
+    /// strings are written as they are and other values as JSON.
+    pub fn export_csv(&self, path: &str) -> Result<(), NeemoError> {
+        let mut rows: Vec<(String, Document)> = Vec::new();
+        for chunk in self.document_chunks() {
+            for (key, doc_data) in chunk? {
+                if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
+                    rows.push((String::from_utf8_lossy(&key).into_owned(), doc));
+                }
+            }
+        }
+        let fields: BTreeSet<&str> = rows.iter().flat_map(|(_, doc)| doc.data.keys().map(String::as_str)).collect();
+
+        let mut writer = io::BufWriter::new(File::create(path)?);
+        let header: Vec<String> = std::iter::once(CSV_KEY_COLUMN).chain(fields.iter().copied()).map(csv_cell).collect();
+        writeln!(writer, "{}", header.join(","))?;
+        for (key, doc) in &rows {
+            let mut cells = vec![csv_cell(key)];
+            for field in &fields {
+                cells.push(match doc.data.get(*field) {
+                    Some(Value::String(text)) => csv_cell(text),
+                    Some(value) => csv_cell(&value.to_string()),
+                    None => String::new(),
+                });
+            }
+            writeln!(writer, "{}", cells.join(","))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Imports documents from CSV whose first row names the fields. Cells
+    /// holding valid JSON are parsed as JSON and any other cell is kept as
+    /// a string; blank cells leave the field out. Documents are stored
+    /// under the `_key` column when there is one, and otherwise keyed like
+    /// document-only lines in `import`. Returns how many were imported.
+    pub fn import_csv(&self, path: &str) -> Result<usize, NeemoError> {
+        let mut rows = parse_csv(&std::fs::read_to_string(path)?).into_iter();
+        let Some(header) = rows.next() else {
+            return Ok(0);
+        };
+        let keyed_by_field = lock(&self.id_field).is_some();
+
+        let mut docs = Vec::new();
+        for row in rows.filter(|row| row.iter().any(|cell| !cell.is_empty())) {
+            let mut key = None;
+            let mut doc = Document { data: HashMap::new() };
+            for (field, cell) in header.iter().zip(row) {
+                if field == CSV_KEY_COLUMN {
+                    key = Some(cell);
+                } else if !cell.is_empty() {
+                    let value = serde_json::from_str(&cell).unwrap_or(Value::String(cell));
+                    doc.data.insert(field.clone(), value);
+                }
+            }
+            let key = match key {
+                Some(key) => key,
+                None if keyed_by_field => self.id_key(&doc)?,
+                None => serde_json::to_string(&doc)?,
+            };
+            docs.push((key, doc));
+        }
+        self.insert_many(&docs)?;
+        Ok(docs.len())
+    }
+
+    /// Supports importing data.
+    ///
+    /// Each document is stored under the key recorded by `export`. Lines
+    /// from older exports hold only a document, which is keyed by the ID
+    /// field if one is set and by its own JSON otherwise. Settings from a
+    /// `<path>.meta.json` sidecar are applied before any document is loaded,
+    /// so an ID field's uniqueness holds during the load.
+    pub fn import(&self, path: &str) -> Result<(), NeemoError> {
+        match self.import_batched(path, DEFAULT_IMPORT_BATCH_SIZE, 0).error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Imports documents in batches of `batch_size` lines, skipping the first
+    /// `resume_after` lines. Each batch is written while other writes wait and
+    /// flushed before the next begins, and a batch containing an invalid key
+    /// or a document breaking a schema is rejected as a whole. On failure the report's `last_committed_line`
+    /// is the value to pass as `resume_after` to continue.
+    pub fn import_batched(&self, path: &str, batch_size: usize, resume_after: usize) -> ImportReport {
+        let mut report = ImportReport { last_committed_line: resume_after, ..ImportReport::default() };
+        let result = File::open(path)
+            .map_err(NeemoError::from)
+            .and_then(|file| self.import_lines(path, BufReader::new(file), batch_size.max(1), None, &mut report));
+        if let Err(e) = result {
+            report.error = Some(e);
+        }
+        report
+    }
+
+    /// Imports like `import`, settling key conflicts by `policy` instead of
+    /// the default, which overwrites unless an ID field is set. Documents
+    /// left out by `ImportPolicy::Skip` are counted as skipped.
+    pub fn import_with_policy(&self, path: &str, policy: ImportPolicy) -> Result<(), NeemoError> {
+        let mut report = ImportReport::default();
+        let reader = BufReader::new(File::open(path)?);
+        self.import_lines(path, reader, DEFAULT_IMPORT_BATCH_SIZE, Some(policy), &mut report)
+    }
+
+    /// Imports a file written by `export_archive`. Files starting with the
+    /// gzip magic bytes, or named `*.gz`, are decompressed on the way in, so
+    /// a plain `export` file imports as well.
+    #[cfg(feature = "flate2")]
+    pub fn import_archive(&self, path: &str) -> Result<(), NeemoError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let gzipped = reader.fill_buf()?.starts_with(&GZIP_MAGIC)
+            || Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz"));
+        let reader: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Box::new(reader)
+        };
+        let mut report = ImportReport::default();
+        self.import_lines(path, reader, DEFAULT_IMPORT_BATCH_SIZE, None, &mut report)
+    }
+
+    /// Imports lines from `reader`. Without a `policy`, key conflicts fail
+    /// when an ID field is set, as a duplicate ID would, and overwrite
+    /// otherwise.
+    fn import_lines(
+        &self,
+        path: &str,
+        reader: impl BufRead,
+        batch_size: usize,
+        policy: Option<ImportPolicy>,
+        report: &mut ImportReport,
+    ) -> Result<(), NeemoError> {
+        if self.read_only {
+            return Err(NeemoError::ReadOnly);
+        }
+        if let Ok(meta_file) = File::open(metadata_path(path)) {
+            let meta = serde_json::from_reader(BufReader::new(meta_file))?;
+            self.apply_metadata(meta)?;
+        }
+        let keyed_by_field = lock(&self.id_field).is_some();
+        let policy = policy.unwrap_or(if keyed_by_field { ImportPolicy::Fail } else { ImportPolicy::Overwrite });
+
+        let mut lines = reader.lines().enumerate().skip(report.last_committed_line).peekable();
+
+        while lines.peek().is_some() {
+            let mut batch = Vec::new();
+            let mut skipped = 0;
+            let mut last_line = report.last_committed_line;
+            for (number, line) in lines.by_ref().take(batch_size) {
+                last_line = number + 1;
+                let line = line?;
+                if let Ok(ExportRecord { key, doc }) = serde_json::from_str(&line) {
+                    batch.push((key, doc));
+                    continue;
+                }
+                match serde_json::from_str::<Document>(&line) {
+                    Ok(doc) if keyed_by_field => batch.push((self.id_key(&doc)?, doc)),
+                    Ok(doc) => {
+                        let key = serde_json::to_string(&doc)?;
+                        batch.push((key, doc));
+                    }
+                    Err(_) => skipped += 1,
+                }
+            }
+
+            let _writer = self.write_lock()?;
+            let stores = self.stores();
+            let (db, index) = (&stores.db, &stores.index);
+            if policy != ImportPolicy::Overwrite {
+                let mut seen = HashSet::new();
+                let mut kept = Vec::with_capacity(batch.len());
+                for (key, doc) in batch {
+                    let stored = db.contains_key(key.as_bytes())? && !self.is_expired(key.as_bytes());
+                    if seen.contains(&key) || stored {
+                        if policy == ImportPolicy::Fail {
+                            return Err(NeemoError::DuplicateKey(key));
+                        }
+                        skipped += 1;
+                        continue;
+                    }
+                    seen.insert(key.clone());
+                    kept.push((key, doc));
+                }
+                batch = kept;
+            }
+            for (key, doc) in &batch {
+                self.validate(key, doc)?;
+            }
+            for (key, doc) in &batch {
+                self.write_document(db, index, key, doc)?;
+            }
+            db.flush()?;
+            index.flush()?;
+            for store in stores.side_stores() {
+                store.flush()?;
+            }
+
+            report.imported += batch.len();
+            report.skipped += skipped;
+            report.last_committed_line = last_line;
+        }
+        Ok(())
+    }
+
+    /// Writes a document and its index entries while the write lock is held,
+    /// dropping the entries of a replaced document that no longer apply.
+    fn write_document(&self, db: &S, index: &S, key: &str, doc: &Document) -> Result<(), NeemoError> {
+        let serialized = serde_json::to_string(doc)?;
+        let old_data = db.insert(key.as_bytes(), serialized.as_bytes())?;
+        if let Some(old_data) = &old_data {
+            self.archive(key, old_data)?;
+        }
+        let old = old_data.and_then(|old| serde_json::from_slice::<Document>(&old).ok());
+        self.reindex(index, key, old.as_ref(), doc)?;
+        self.clear_expiry(key)?;
+        self.invalidate_cached(key);
+        Ok(())
+    }
+}
+
+impl<S: KvStore + WatchStore> Neemo<S> {
+    /// Returns the changes to documents whose key starts with `prefix`, in
+    /// the order they are made, from now on. The iterator blocks until the
+    /// next change. A swap shows up as two inserts.
+    pub fn watch(&self, prefix: &str) -> impl Iterator<Item = ChangeEvent> {
+        self.stores().db.watch_prefix(prefix.as_bytes()).filter_map(|event| match event {
+            sled::Event::Insert { key, value } => {
+                let key = String::from_utf8_lossy(&key).into_owned();
+                match serde_json::from_slice(&value) {
+                    Ok(doc) => Some(ChangeEvent::Insert { key, doc }),
+                    Err(_) => {
+                        warn!("Skipping corrupt document '{}'", key);
+                        None
+                    }
+                }
+            }
+            sled::Event::Remove { key } => Some(ChangeEvent::Delete { key: String::from_utf8_lossy(&key).into_owned() }),
+        })
+    }
+}
+
+impl<S: KvStore> Drop for Neemo<S> {
+    /// Flushes every store so buffered writes are not lost when the handle
+    /// goes away. A failure is logged; use `close` to handle it. A read-only
+    /// handle has nothing to flush and leaves the files alone.
+    fn drop(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = self.flush() {
+            error!("Failed to flush database on close: {}", e);
+        }
+    }
+}
+
+/// Returns false for a corrupt document, logging a warning, so reads
+/// spanning many documents can leave it out.
+fn is_readable<T>(item: &Result<T, NeemoError>) -> bool {
+    match item {
+        Err(NeemoError::Corrupt(key)) => {
+            warn!("Skipping corrupt document '{}'", key);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Builds the blob store key of a document's binary field. A NUL byte
+/// separates the two, so `blob_key(key, "")` prefixes every blob of `key`.
+fn blob_key(key: &str, field: &str) -> Vec<u8> {
+    [key.as_bytes(), b"\0", field.as_bytes()].concat()
+}
+
+/// Builds the prefix of the history store keys of a document's versions,
+/// each of which appends the version number as 16 hex digits so versions
+/// sort oldest first.
+fn version_prefix(key: &str) -> Vec<u8> {
+    [key.as_bytes(), b"\0"].concat()
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+fn now_millis() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    u64::try_from(since_epoch.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Reads an expiry time stored by `insert_with_ttl`. Unreadable values
+/// never expire.
+fn decode_expiry(bytes: &[u8]) -> u64 {
+    <[u8; 8]>::try_from(bytes).map_or(u64::MAX, u64::from_be_bytes)
+}
+
+/// Returns the directory beside the store `tree` of the database at `path`
+/// that `checkpoint` uses for its copy or the original, by `suffix`.
+fn checkpoint_dir(path: &Path, tree: &str, suffix: &str) -> std::path::PathBuf {
+    path.join(format!("{}.{}", tree, suffix))
+}
+
+/// Finishes or undoes a `checkpoint` interrupted by a crash: a store that
+/// was moved aside takes the complete copy's place, and leftovers of a
+/// finished or abandoned checkpoint are removed.
+fn finish_checkpoint(path: &Path) -> io::Result<()> {
+    for tree in ["data", "index", "text", "expiry", "blobs", "history"] {
+        let (copy, old) = (checkpoint_dir(path, tree, "checkpoint"), checkpoint_dir(path, tree, "old"));
+        if !path.join(tree).exists() {
+            if copy.is_dir() {
+                std::fs::rename(&copy, path.join(tree))?;
+            } else if old.is_dir() {
+                std::fs::rename(&old, path.join(tree))?;
+            }
+        }
+        for leftover in [copy, old] {
+            if leftover.is_dir() {
+                std::fs::remove_dir_all(leftover)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recursively copies the directory `from` to `to`.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of the sled tree names holding collections, keeping them apart from
+/// sled's default tree.
+const COLLECTION_TREE_PREFIX: &str = "collection:";
+
+/// Replaces every tree of `live` with the same-named tree of `backup`,
+/// emptying trees the backup lacks.
+fn restore_trees(live: &Db, backup: Option<&Db>) -> Result<(), NeemoError> {
+    let mut names: BTreeSet<sled::IVec> = live.tree_names().into_iter().collect();
+    names.extend(backup.iter().flat_map(|backup| backup.tree_names()));
+    for name in names {
+        let tree = live.open_tree(&name)?;
+        tree.clear()?;
+        if let Some(backup) = backup.filter(|backup| backup.tree_names().contains(&name)) {
+            for (key, value) in backup.open_tree(&name)?.iter().flatten() {
+                tree.insert(key, value)?;
+            }
+        }
+    }
+    live.flush()?;
+    Ok(())
+}
+
+/// Returns the path of the settings sidecar for an export file.
+fn metadata_path(path: &str) -> String {
+    format!("{}.meta.json", path)
+}
+
+/// Header of the CSV column holding document keys.
+const CSV_KEY_COLUMN: &str = "_key";
+
+/// Rebuilds `value` with object keys in sorted order at every level. Object
+/// key order otherwise depends on whether serde_json's `preserve_order`
+/// feature is enabled somewhere in the build.
+fn canonical_json(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<String, Value> = map.into_iter().map(|(key, value)| (key, canonical_json(value))).collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(canonical_json).collect()),
+        other => other,
+    }
+}
+
+/// Quotes a CSV cell if it holds a comma, quote or line break.
+fn csv_cell(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Splits CSV text into rows of cells. Quoted cells may hold commas, line
+/// breaks and doubled quotes.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut cell)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Starts the index keys of `field`. Backslashes and colons in the name are
+/// escaped with a backslash, so the first bare `:` always ends the field and
+/// no field's keys share a prefix with another's.
+fn field_prefix(field: &str) -> String {
+    let mut prefix = String::with_capacity(field.len() + 1);
+    for c in field.chars() {
+        if matches!(c, ':' | '\\') {
+            prefix.push('\\');
+        }
+        prefix.push(c);
+    }
+    prefix.push(':');
+    prefix
+}
+
+/// Starts the per-element index keys of an array `field`. The unescaped
+/// `\*` cannot occur in a field prefix, so these never mix with value keys.
+fn element_prefix(field: &str) -> String {
+    let mut prefix = field_prefix(field);
+    prefix.pop();
+    prefix.push_str("\\*:");
+    prefix
+}
+
+/// Key under which the index store records the indexed fields. The leading
+/// NUL keeps it apart from `field:value` entries.
+const INDEX_DEFINITIONS_KEY: &[u8] = b"\0indexes";
+
+/// Key under which the index store holds writes being committed to stores
+/// that cannot share a transaction, until they have all been applied.
+const PENDING_WRITES_KEY: &[u8] = b"\0pending";
+
+/// Writes to a document store and its index store recorded before they are
+/// applied, so a commit interrupted midway can be finished.
+#[derive(Serialize, Deserialize)]
+struct PendingWrites {
+    data: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    index: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl PendingWrites {
+    /// Applies the writes, which may already be partly applied, and then
+    /// drops the record.
+    fn apply<S: KvStore>(&self, db: &S, index: &S) -> KvResult<()> {
+        for (store, writes) in [(db, &self.data), (index, &self.index)] {
+            for (key, value) in writes {
+                match value {
+                    Some(value) => store.insert(key, value)?,
+                    None => store.remove(key)?,
+                };
+            }
+            store.flush()?;
+        }
+        index.remove(PENDING_WRITES_KEY)?;
+        index.flush()
+    }
+}
+
+/// Applies staged writes to a document store and its index store so that
+/// either all of them take effect or none do. Stores that cannot share a
+/// transaction, such as two sled databases, get the writes recorded in the
+/// index store first, and `finish_pending_writes` completes them if the
+/// process stops before they are all applied.
+fn commit_together<S: KvStore>(db: &S, db_writes: KvWrites, index: &S, index_writes: KvWrites) -> KvResult<()> {
+    if db.apply_together(&db_writes, index, &index_writes)? {
+        return Ok(());
+    }
+    let pending = PendingWrites { data: db_writes.into_iter().collect(), index: index_writes.into_iter().collect() };
+    index.insert(PENDING_WRITES_KEY, &serde_json::to_vec(&pending)?)?;
+    index.flush()?;
+    pending.apply(db, index)
+}
+
+/// Finishes a commit that `commit_together` recorded but did not complete.
+fn finish_pending_writes<S: KvStore>(db: &S, index: &S) -> KvResult<()> {
+    let Some(record) = index.get(PENDING_WRITES_KEY)? else {
+        return Ok(());
+    };
+    warn!("Finishing a commit interrupted before all of its writes were applied");
+    serde_json::from_slice::<PendingWrites>(&record)?.apply(db, index)
+}
+
+/// Reads the indexed fields recorded in an index store. A store without a
+/// record predates explicit indexes and indexed every field, so the fields
+/// of its entries are returned.
+fn load_index_definitions<S: KvStore>(index: &S) -> BTreeSet<String> {
+    match index.get(INDEX_DEFINITIONS_KEY) {
+        Ok(Some(definitions)) => serde_json::from_slice(&definitions).unwrap_or_default(),
+        _ => index.iter()
+            .flatten()
+            .filter_map(|(index_key, _)| {
+                let index_key = String::from_utf8_lossy(&index_key).into_owned();
+                index_key.split_once(':').map(|(field, _)| field.to_string())
+            })
+            .collect(),
+    }
+}
+
+/// Moves index entries whose value is a plain JSON number to keys built by
+/// `sortable_number`.
+fn upgrade_number_keys<S: KvStore>(index: &S) -> KvResult<()> {
+    for (index_key, keys) in index.iter().flatten() {
+        let Some((field, encoded)) = std::str::from_utf8(&index_key).ok().and_then(|key| key.split_once(':')) else {
+            continue;
+        };
+        let Ok(Value::Number(n)) = serde_json::from_str::<Value>(encoded) else {
+            continue;
+        };
+        let doc_keys = decode_index_keys(&keys);
+        let doc_keys: Vec<&str> = doc_keys.iter().map(String::as_str).collect();
+        add_index_keys(index, &format!("{}:{}{}", field, sortable_number(&n), encoded), &doc_keys)?;
+        index.remove(&index_key)?;
+    }
+    Ok(())
+}
+
+/// Records the indexed fields in an index store.
+fn save_index_definitions<S: KvStore>(index: &S, indexes: &BTreeSet<String>) -> KvResult<()> {
+    index.insert(INDEX_DEFINITIONS_KEY, &serde_json::to_vec(indexes)?)?;
+    Ok(())
+}
+
+/// Reads the document keys held by an index entry. Entries written before
+/// the index kept a set of keys per value hold one raw key.
+fn decode_index_keys(bytes: &[u8]) -> Vec<String> {
+    serde_json::from_slice(bytes).unwrap_or_else(|_| vec![String::from_utf8_lossy(bytes).into_owned()])
+}
+
+/// Adds `doc_key` to the set of keys stored under `index_key`.
+fn add_index_key<S: EntryStore + ?Sized>(index: &S, index_key: &str, doc_key: &str) -> KvResult<()> {
+    add_index_keys(index, index_key, &[doc_key])
+}
+
+/// Adds each of `doc_keys` to the set of keys stored under `index_key`,
+/// rewriting the entry at most once.
+fn add_index_keys<S: EntryStore + ?Sized>(index: &S, index_key: &str, doc_keys: &[&str]) -> KvResult<()> {
+    let mut keys = index.read_entry(index_key.as_bytes())?.map(|keys| decode_index_keys(&keys)).unwrap_or_default();
+    let mut present: HashSet<String> = keys.iter().cloned().collect();
+    let before = keys.len();
+    for doc_key in doc_keys {
+        if present.insert(doc_key.to_string()) {
+            keys.push(doc_key.to_string());
+        }
+    }
+    if keys.len() > before {
+        let encoded = serde_json::to_vec(&keys)?;
+        index.write_entry(index_key.as_bytes(), &encoded)?;
+    }
+    Ok(())
+}
+
+/// Removes `doc_key` from the set stored under `index_key`, dropping the
+/// entry once no keys remain.
+fn remove_index_key<S: EntryStore + ?Sized>(index: &S, index_key: &str, doc_key: &str) -> KvResult<()> {
+    let Some(keys) = index.read_entry(index_key.as_bytes())? else {
+        return Ok(());
+    };
+    let keys: Vec<String> = decode_index_keys(&keys).into_iter().filter(|key| key != doc_key).collect();
+    if keys.is_empty() {
+        index.remove_entry(index_key.as_bytes())?;
+    } else {
+        let encoded = serde_json::to_vec(&keys)?;
+        index.write_entry(index_key.as_bytes(), &encoded)?;
+    }
+    Ok(())
+}
+
+/// Locks a mutex, recovering it if a thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        warn!("Recovering from a poisoned lock");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Encodes a number so that byte order matches numeric order: `!` followed by
+/// the IEEE 754 bits as 16 hex digits, with the sign bit flipped for
+/// positive numbers and every bit flipped for negative ones.
+fn sortable_number(n: &serde_json::Number) -> String {
+    // Adding zero turns -0.0 into 0.0 so both share a key.
+    let bits = (n.as_f64().unwrap_or(0.0) + 0.0).to_bits();
+    let bits = if bits >> 63 == 1 { !bits } else { bits | 1 << 63 };
+    format!("!{:016x}", bits)
+}
+
+/// Recovers the value from the part of an index key after `field:`, or
+/// `None` for a capped value.
+fn index_key_value(encoded: &[u8]) -> Option<Value> {
+    let encoded = match encoded.first() {
+        Some(b'!') => encoded.get(17..)?,
+        _ => encoded,
+    };
+    serde_json::from_slice(encoded).ok()
+}
+
+/// 64-bit FNV-1a hash, stable across runs and platforms.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Ranks JSON types in the order null < bool < number < string < array < object.
+fn value_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+/// Orders JSON values: numbers numerically, strings and booleans naturally,
+/// and values of different types by `value_rank`.
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Number(x), Value::Number(y)) => {
+            x.as_f64().unwrap_or(0.0).total_cmp(&y.as_f64().unwrap_or(0.0))
+        }
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => {
+            a.to_string().cmp(&b.to_string())
+        }
+        _ => value_rank(a).cmp(&value_rank(b)),
+    }
+}
+
+/// Orders two possibly absent field values for sorting. `desc` reverses the
+/// order of present values only; `null` and missing values are placed by
+/// `nulls`, with `null` ahead of missing.
+fn compare_fields(a: Option<&Value>, b: Option<&Value>, desc: bool, nulls: NullOrder) -> Ordering {
+    let absence = |value: Option<&Value>| match value {
+        Some(Value::Null) => 1,
+        None => 2,
+        Some(_) => 0,
+    };
+    match (a, b) {
+        (Some(x), Some(y)) if !x.is_null() && !y.is_null() => {
+            let ordering = compare_values(x, y);
+            if desc { ordering.reverse() } else { ordering }
+        }
+        _ => match (absence(a), absence(b)) {
+            (0, _) if nulls == NullOrder::Last => Ordering::Less,
+            (0, _) => Ordering::Greater,
+            (_, 0) if nulls == NullOrder::Last => Ordering::Greater,
+            (_, 0) => Ordering::Less,
+            (x, y) => x.cmp(&y),
+        },
+    }
+}
+
+/// Returns true if `value` lies between `start` and `end` and has the same
+/// type as them, as `range_query_with` decides. A `null` bound is open.
+fn in_range(value: &Value, start: &Value, end: &Value, end_mode: RangeEnd) -> bool {
+    let kind = if start.is_null() { end } else { start };
+    (kind.is_null() || value_rank(value) == value_rank(kind))
+        && (start.is_null() || compare_values(value, start).is_ge())
+        && (end.is_null() || match end_mode {
+            RangeEnd::Excluded => compare_values(value, end).is_lt(),
+            RangeEnd::Included => compare_values(value, end).is_le(),
+        })
+}
+
+/// Merges `patch` into `target`: objects are merged key by key, recursively,
+/// and any other value replaces `target`.
+fn merge_value(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+/// Returns true if `matches` accepts any string within `value`.
+fn value_contains_text(value: &Value, matches: &dyn Fn(&str) -> bool) -> bool {
+    match value {
+        Value::String(text) => matches(text),
+        Value::Array(items) => items.iter().any(|item| value_contains_text(item, matches)),
+        Value::Object(map) => map.values().any(|item| value_contains_text(item, matches)),
+        _ => false,
+    }
+}
+
+/// Lists the fields a document is indexed under with their values. Fields
+/// of nested objects are also listed under their dotted path.
+fn index_fields(doc: &Document) -> Vec<(String, &Value)> {
+    fn collect<'a>(path: String, value: &'a Value, fields: &mut Vec<(String, &'a Value)>) {
+        if let Value::Object(map) = value {
+            for (key, nested) in map {
+                collect(format!("{}.{}", path, key), nested, fields);
+            }
+        }
+        fields.push((path, value));
+    }
+    let mut fields = Vec::new();
+    for (field, value) in &doc.data {
+        collect(field.clone(), value, &mut fields);
+    }
+    fields
+}
+
+/// Counts the occurrences of each word across every string within a document.
+fn document_words(doc: &Document) -> BTreeMap<String, usize> {
+    fn collect(value: &Value, counts: &mut BTreeMap<String, usize>) {
+        match value {
+            Value::String(text) => {
+                for word in words(text) {
+                    *counts.entry(word).or_insert(0) += 1;
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect(item, counts)),
+            Value::Object(map) => map.values().for_each(|item| collect(item, counts)),
+            _ => {}
+        }
+    }
+    let mut counts = BTreeMap::new();
+    doc.data.values().for_each(|value| collect(value, &mut counts));
+    counts
+}
+
+/// Splits text into lowercased words at every non-alphanumeric character.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Lines written per batch when importing.
+pub const DEFAULT_IMPORT_BATCH_SIZE: usize = 1_000;
+
+/// Documents read from the document store at a time when exporting.
+const EXPORT_CHUNK_SIZE: usize = 1_000;
+
+/// First two bytes of every gzip stream.
+#[cfg(feature = "flate2")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupted_commit_is_finished_on_open() {
+        let (db, index) = (MemoryStore::new(), MemoryStore::new());
+        let doc = br#"{"data":{"role":"admin"}}"#.to_vec();
+        let entry = format!("{}\"admin\"", field_prefix("role")).into_bytes();
+        let pending = PendingWrites {
+            data: vec![(b"a".to_vec(), Some(doc.clone())), (b"gone".to_vec(), None)],
+            index: vec![(entry, Some(br#"["a"]"#.to_vec())), (INDEX_DEFINITIONS_KEY.to_vec(), Some(br#"["role"]"#.to_vec()))],
+        };
+        // Only the first write reached the store before the interruption.
+        db.insert(b"a", &doc).unwrap();
+        db.insert(b"gone", b"{}").unwrap();
+        index.insert(PENDING_WRITES_KEY, &serde_json::to_vec(&pending).unwrap()).unwrap();
+
+        let neemo = Neemo::with_stores(db, index);
+        assert_eq!(neemo.stores().index.get(PENDING_WRITES_KEY).unwrap(), None);
+        assert!(neemo.get("gone").is_none());
+        assert_eq!(neemo.query_with_keys("role", Value::from("admin")).unwrap().len(), 1);
+    }
+}
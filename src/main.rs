@@ -1,227 +1,219 @@
-use sled::Db;
-use serde::{Serialize, Deserialize};
-use serde_json::{self, Value};
+use neemo::{BatchOp, Document, Neemo, NeemoError, RangeEnd, SearchResults, TextMatch, DEFAULT_IMPORT_BATCH_SIZE};
+use serde_json::Value;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::io::{self, Write, BufReader, BufRead};
 use std::fs::File;
-use std::sync::{Arc, Mutex};
+use std::io::{self, Write};
+use std::sync::Arc;
 use std::thread;
 use log::error;
 use simplelog::{Config, LevelFilter, WriteLogger};
+#[cfg(all(unix, feature = "uds"))]
+use neemo::uds;
 
-/// Represents a document in Neemo.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Document {
-    pub data: HashMap<String, Value>,
+/// Parses an `ORDER BY` clause such as `a ASC, b DESC` into sort keys,
+/// where `true` marks a descending key. Keys default to ascending.
+fn parse_order_by(clause: &str) -> Option<Vec<(String, bool)>> {
+    clause.split(',')
+        .map(|key| match key.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [field] => Some((field.to_string(), false)),
+            [field, dir] if dir.eq_ignore_ascii_case("ASC") => Some((field.to_string(), false)),
+            [field, dir] if dir.eq_ignore_ascii_case("DESC") => Some((field.to_string(), true)),
+            _ => None,
+        })
+        .collect()
 }
 
-/// Represents the Neemo database.
-pub struct Neemo {
-    db: Arc<Mutex<Db>>,
-    index: Arc<Mutex<Db>>,
-    db_path: String,
+/// A comparison operator accepted by `FIND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FindOp {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
 }
 
-impl Neemo {
-    /// Creates a new Neemo instance.
-    pub fn new(path: &str) -> Self {
-        let db = sled::open(format!("{}/data", path)).expect("Failed to open Neemo database");
-        let index = sled::open(format!("{}/index", path)).expect("Failed to open Neemo index");
-        Neemo {
-            db: Arc::new(Mutex::new(db)),
-            index: Arc::new(Mutex::new(index)),
-            db_path: path.to_string(),
+impl FindOp {
+    fn parse(token: &str) -> Option<FindOp> {
+        match token {
+            "=" => Some(FindOp::Eq),
+            "!=" => Some(FindOp::Ne),
+            ">" => Some(FindOp::Gt),
+            "<" => Some(FindOp::Lt),
+            ">=" => Some(FindOp::Ge),
+            "<=" => Some(FindOp::Le),
+            _ => None,
         }
     }
 
-    /// Inserts or updates a document.
-    pub fn insert(&self, key: &str, doc: Document) -> Result<(), String> {
-        let serialized = serde_json::to_string(&doc).map_err(|e| e.to_string())?;
-        self.db.lock().unwrap().insert(key.as_bytes(), serialized.as_bytes()).map_err(|e| e.to_string())?;
-
-        for (field, value) in &doc.data {
-            let index_key = format!("{}:{}", field, serde_json::to_string(value).map_err(|e| e.to_string())?);
-            self.index.lock().unwrap().insert(index_key.as_bytes(), key.as_bytes()).map_err(|e| e.to_string())?;
+    /// Returns true if a field value ordered `ordering` against the operand
+    /// satisfies the operator.
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            FindOp::Eq => ordering == Ordering::Equal,
+            FindOp::Ne => ordering != Ordering::Equal,
+            FindOp::Gt => ordering == Ordering::Greater,
+            FindOp::Lt => ordering == Ordering::Less,
+            FindOp::Ge => ordering != Ordering::Less,
+            FindOp::Le => ordering != Ordering::Greater,
         }
-        Ok(())
-    }
-
-    /// Retrieves a document by key.
-    pub fn get(&self, key: &str) -> Option<Document> {
-        self.db.lock().unwrap().get(key.as_bytes()).ok().flatten().and_then(|value| serde_json::from_slice(&value).ok())
     }
+}
 
-    /// Deletes a document by key.
-    pub fn delete(&self, key: &str) -> Result<(), String> {
-        if let Some(doc_data) = self.db.lock().unwrap().remove(key.as_bytes()).map_err(|e| e.to_string())? {
-            let doc: Document = serde_json::from_slice(&doc_data).map_err(|e| e.to_string())?;
-            for (field, value) in &doc.data {
-                let index_key = format!("{}:{}", field, serde_json::to_string(value).map_err(|e| e.to_string())?);
-                self.index.lock().unwrap().remove(index_key.as_bytes()).map_err(|e| e.to_string())?;
-            }
+/// Orders a field value against a `FIND` operand, numerically when both are
+/// numbers and by their text otherwise. Strings compare by their contents,
+/// so `name = 42` matches `"42"`.
+fn compare_find_operand(value: &Value, operand: &Value) -> Ordering {
+    fn text(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
         }
-        Ok(())
     }
-
-    /// Queries documents based on a field-value pair.
-    pub fn query(&self, field: &str, value: Value) -> Vec<Document> {
-        let index_key = format!("{}:{}", field, serde_json::to_string(&value).unwrap());
-        let mut results = Vec::new();
-        
-        for item in self.index.lock().unwrap().scan_prefix(index_key.as_bytes()) {
-            if let Ok((_, doc_key)) = item {
-                if let Some(doc_data) = self.db.lock().unwrap().get(doc_key).unwrap() {
-                    if let Ok(doc) = serde_json::from_slice(&doc_data) {
-                        results.push(doc);
-                    }
-                }
-            }
-        }
-        results
-    }
-
-    /// Lists all documents.
-    pub fn list(&self) -> Vec<Document> {
-        self.db.lock().unwrap().iter()
-            .filter_map(|item| item.ok())
-            .filter_map(|(_key, value)| serde_json::from_slice(&value).ok())
-            .collect()
-    }
-
-    /// Supports transactions.
-    pub fn transaction<F, T>(&self, f: F) -> T
-    where
-        F: FnOnce(&Db, &Db) -> T,
-    {
-        f(&self.db.lock().unwrap(), &self.index.lock().unwrap())
+    match (value.as_f64(), operand.as_f64()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => text(value).cmp(&text(operand)),
     }
+}
 
-    /// Supports range queries.
-    pub fn range_query(&self, field: &str, start: Value, end: Value) -> Vec<Document> {
-        let start_key = format!("{}:{}", field, serde_json::to_string(&start).unwrap());
-        let end_key = format!("{}:{}", field, serde_json::to_string(&end).unwrap());
-        let mut results = Vec::new();
-
-        for item in self.index.lock().unwrap().range(start_key.as_bytes()..end_key.as_bytes()) {
-            if let Ok((_, doc_key)) = item {
-                if let Some(doc_data) = self.db.lock().unwrap().get(doc_key).unwrap() {
-                    if let Ok(doc) = serde_json::from_slice(&doc_data) {
-                        results.push(doc);
-                    }
-                }
+/// Splits a command line into arguments at whitespace. Double quotes group
+/// text containing spaces into one argument and are removed; a backslash
+/// makes the next character literal, so `\"` is a quote inside an argument.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    // Set once the current argument has begun, so `""` yields an empty one.
+    let mut started = false;
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                token.extend(chars.next());
+                started = true;
             }
-        }
-        results
-    }
-
-    /// Supports full-text search.
-    pub fn full_text_search(&self, query: &str) -> Vec<Document> {
-        let mut results = Vec::new();
-
-        for item in self.db.lock().unwrap().iter() {
-            if let Ok((_, doc_data)) = item {
-                if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
-                    for value in doc.data.values() {
-                        if let Value::String(text) = value {
-                            if text.contains(query) {
-                                results.push(doc.clone());
-                                break;
-                            }
-                        }
-                    }
-                }
+            '"' => {
+                quoted = !quoted;
+                started = true;
             }
-        }
-        results
-    }
-
-    /// Supports aggregation queries.
-    pub fn aggregate(&self, field: &str, op: &str) -> Option<Value> {
-        let mut sum = 0.0;
-        let mut count = 0;
-
-        for item in self.db.lock().unwrap().iter() {
-            if let Ok((_, doc_data)) = item {
-                if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
-                    if let Some(value) = doc.data.get(field) {
-                        if let Value::Number(num) = value {
-                            if let Some(f) = num.as_f64() {
-                                sum += f;
-                                count += 1;
-                            }
-                        }
-                    }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    tokens.push(std::mem::take(&mut token));
+                    started = false;
                 }
             }
-        }
-
-        match op {
-            "sum" => serde_json::Number::from_f64(sum).map(Value::Number),
-            "count" => Some(Value::Number(count.into())),
-            "avg" => serde_json::Number::from_f64(sum / count as f64).map(Value::Number),
-            _ => None,
+            c => {
+                token.push(c);
+                started = true;
+            }
         }
     }
-
-    /// Supports batch operations.
-    pub fn batch<F>(&self, f: F)
-    where
-        F: FnOnce(&Db, &Db),
-    {
-        f(&self.db.lock().unwrap(), &self.index.lock().unwrap());
+    if started {
+        tokens.push(token);
     }
+    tokens
+}
 
-    /// Supports exporting data.
-    pub fn export(&self, path: &str) -> Result<(), String> {
-        let file = File::create(path).map_err(|e| e.to_string())?;
-        let mut writer = io::BufWriter::new(file);
+/// Reads a command argument as JSON, taking it as a plain string when it
+/// isn't valid JSON.
+fn parse_value(arg: &str) -> Value {
+    serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.to_string()))
+}
 
-        for item in self.db.lock().unwrap().iter() {
-            if let Ok((_, doc_data)) = item {
-                if let Ok(doc) = serde_json::from_slice::<Document>(&doc_data) {
-                    serde_json::to_writer(&mut writer, &doc).map_err(|e| e.to_string())?;
-                    writer.write_all(b"\n").map_err(|e| e.to_string())?;
-                }
+/// Prompts for `field=value` lines until an empty one, collecting the fields
+/// whose value is valid JSON.
+fn read_fields() -> Document {
+    let mut doc = Document { data: HashMap::new() };
+    println!("Enter fields in 'field=value' format (empty line to finish):");
+    loop {
+        print!("Field: ");
+        io::stdout().flush().unwrap();
+        let mut field_input = String::new();
+        io::stdin().read_line(&mut field_input).unwrap();
+        let field_input = field_input.trim();
+        if field_input.is_empty() { break; }
+        if let Some((field, value)) = field_input.split_once('=') {
+            if let Ok(json_value) = serde_json::from_str(value) {
+                doc.data.insert(field.to_string(), json_value);
             }
         }
-        Ok(())
     }
+    doc
+}
 
-    /// Supports importing data.
-    pub fn import(&self, path: &str) -> Result<(), String> {
-        let file = File::open(path).map_err(|e| e.to_string())?;
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if let Ok(doc) = serde_json::from_str::<Document>(&line) {
-                    self.insert(&serde_json::to_string(&doc).map_err(|e| e.to_string())?, doc)?;
-                }
-            }
-        }
-        Ok(())
+/// Checks a name given to `CREATE DATABASE` or `USE DATABASE`. The name is
+/// joined onto `databases/`, so it must be a single path component ending in
+/// `.nemo`.
+fn validate_db_name(name: &str) -> Result<(), String> {
+    let Some(stem) = name.strip_suffix(".nemo") else {
+        return Err("Database name must end with '.nemo'".to_string());
+    };
+    if stem.is_empty() || stem.contains("..") || name.contains(['/', '\\']) {
+        return Err(format!("Invalid database name '{}'", name));
     }
+    Ok(())
+}
 
-    /// Supports backup and restore.
-    pub fn backup(&self, path: &str) -> Result<(), String> {
-        self.db.lock().unwrap().flush().map_err(|e| e.to_string())?;
-        std::fs::copy(&self.db_path, path).map_err(|e| e.to_string())?;
-        Ok(())
+/// Returns the database at `path`, opening it unless it is already open.
+fn open_database(databases: &mut HashMap<String, Arc<Neemo>>, path: &str) -> Result<Arc<Neemo>, NeemoError> {
+    if let Some(neemo) = databases.get(path) {
+        return Ok(Arc::clone(neemo));
     }
+    let neemo = Arc::new(Neemo::open(path)?);
+    databases.insert(path.to_string(), Arc::clone(&neemo));
+    Ok(neemo)
+}
 
-    pub fn restore(&self, path: &str) -> Result<(), String> {
-        std::fs::copy(path, &self.db_path).map_err(|e| e.to_string())?;
-        self.db.lock().unwrap().flush().map_err(|e| e.to_string())?;
-        Ok(())
+/// Prints search matches followed by a notice when the output was truncated.
+fn print_search_results(results: Result<SearchResults, NeemoError>) {
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => return println!("Search failed: {}", e),
+    };
+    for doc in &results.documents {
+        println!("{:?}", doc);
     }
+    if let Some(notice) = truncation_notice(&results) {
+        println!("{}", notice);
+    }
+}
+
+/// Describes how many matches a truncated search shows. The search stops at
+/// the first match past its limit, so the total is only known to be at least
+/// one more than those shown.
+fn truncation_notice(results: &SearchResults) -> Option<String> {
+    let shown = results.documents.len();
+    results.truncated.then(|| format!("(showing {} of {}+ matches)", shown, shown + 1))
 }
 
+/// Number of matches shown by `SEARCH` when no limit is given.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// How many documents `AGGREGATE` scans between progress lines.
+const AGGREGATE_PROGRESS_INTERVAL: usize = 10_000;
+
 fn main() {
     let db_path = "neemo_db";
-    let neemo = Arc::new(Neemo::new(db_path));
+    let mut neemo = match Neemo::open(db_path) {
+        Ok(neemo) => Arc::new(neemo),
+        Err(e) => {
+            eprintln!("Failed to open Neemo database: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // Databases stay open once used: background commands may still hold a
+    // handle, and sled refuses to open a database twice.
+    let mut databases = HashMap::from([(db_path.to_string(), Arc::clone(&neemo))]);
 
-    // Initialize logging
-    WriteLogger::init(LevelFilter::Info, Config::default(), File::create("neemo.log").unwrap()).unwrap();
+    // Initialize logging; NEEMO_LOG=debug also logs each insert, delete and
+    // query with its timing.
+    let log_level = std::env::var("NEEMO_LOG").ok().and_then(|level| level.parse().ok()).unwrap_or(LevelFilter::Info);
+    WriteLogger::init(log_level, Config::default(), File::create("neemo.log").unwrap()).unwrap();
+
+    #[cfg(all(unix, feature = "uds"))]
+    let mut socket_server: Option<uds::UdsServer> = None;
 
     loop {
         print!("Neemo > ");
@@ -229,52 +221,95 @@ fn main() {
         let mut input = String::new();
         io::stdin().read_line(&mut input).expect("Failed to read input");
         let command = input.trim().to_string(); // Convert to owned String
-        let parts: Vec<String> = command.split_whitespace().map(String::from).collect(); // Convert to owned Strings
+        let parts = tokenize(&command);
 
         match parts.as_slice() {
             [cmd, db, name] if cmd == "CREATE" && db == "DATABASE" => {
-                let name = name.to_string();
-                if !name.ends_with(".nemo") {
-                    println!("Database name must end with '.nemo'");
+                if let Err(message) = validate_db_name(name) {
+                    println!("{}", message);
                 } else {
-                    let db_path = format!("databases/{}", name);
-                    let _neemo_clone = Arc::new(Neemo::new(&db_path));
-                    println!("Database '{}' created.", name);
+                    match open_database(&mut databases, &format!("databases/{}", name)) {
+                        Ok(created) => {
+                            neemo = created;
+                            println!("Database '{}' created and selected.", name);
+                        }
+                        Err(e) => println!("Failed to create database '{}': {}", name, e),
+                    }
                 }
             }
-            [cmd, db, name] if cmd == "USE" && db == "DATABASE" => {
-                let name = name.to_string();
-                if !name.ends_with(".nemo") {
-                    println!("Database name must end with '.nemo'");
+            [cmd, kind, field] if cmd == "CREATE" && kind == "INDEX" => {
+                match neemo.create_index(field) {
+                    Ok(_) => println!("Index on '{}' created.", field),
+                    Err(e) => println!("Failed to create index on '{}': {}", field, e),
+                }
+            }
+            [cmd, kind, field] if cmd == "DROP" && kind == "INDEX" => {
+                match neemo.drop_index(field) {
+                    Ok(_) => println!("Index on '{}' dropped.", field),
+                    Err(e) => println!("Failed to drop index on '{}': {}", field, e),
+                }
+            }
+            [cmd] if cmd == "INDEXES" => {
+                let indexes = neemo.list_indexes();
+                if indexes.is_empty() {
+                    println!("No indexes defined.");
                 } else {
-                    let db_path = format!("databases/{}", name);
-                    let _neemo_clone = Arc::new(Neemo::new(&db_path));
-                    println!("Switched to database '{}'.", name);
+                    for field in indexes {
+                        println!("{}", field);
+                    }
                 }
             }
-            [cmd, key] if cmd == "INSERT" => {
-                let key = key.to_string();
-                let mut doc = Document { data: HashMap::new() };
-                println!("Enter fields in 'field=value' format (empty line to finish):");
-                loop {
-                    print!("Field: ");
-                    io::stdout().flush().unwrap();
-                    let mut field_input = String::new();
-                    io::stdin().read_line(&mut field_input).unwrap();
-                    let field_input = field_input.trim();
-                    if field_input.is_empty() { break; }
-                    if let Some((field, value)) = field_input.split_once('=') {
-                        if let Ok(json_value) = serde_json::from_str(value) {
-                            doc.data.insert(field.to_string(), json_value);
+            [cmd, db, name] if cmd == "USE" && db == "DATABASE" && name == "DEFAULT" => {
+                neemo = Arc::clone(&databases[db_path]);
+                println!("Switched to the default database.");
+            }
+            [cmd, db, name] if cmd == "USE" && db == "DATABASE" => {
+                if let Err(message) = validate_db_name(name) {
+                    println!("{}", message);
+                } else {
+                    match open_database(&mut databases, &format!("databases/{}", name)) {
+                        Ok(selected) => {
+                            neemo = selected;
+                            println!("Switched to database '{}'.", name);
                         }
+                        Err(e) => println!("Failed to open database '{}': {}", name, e),
                     }
                 }
-                let neemo_clone = Arc::clone(&neemo);
-                thread::spawn(move || {
-                    if let Err(e) = neemo_clone.insert(&key, doc) {
+            }
+            [cmd, key] if cmd == "INSERT" => {
+                let key = key.to_string();
+                let doc = read_fields();
+                // Writes finish before the prompt returns, so the next command sees them.
+                match neemo.insert(&key, doc) {
+                    Ok(()) => println!("Document '{}' inserted.", key),
+                    Err(e) => {
                         error!("Failed to insert document: {}", e);
+                        println!("Failed to insert document '{}': {}", key, e);
                     }
-                });
+                }
+            }
+            [cmd, key] if cmd == "UPDATE" => {
+                let Some(current) = neemo.get(key) else {
+                    println!("Key '{}' not found.", key);
+                    continue;
+                };
+                println!("Current: {:?}", current);
+                let fields = read_fields();
+                match neemo.patch(key, fields) {
+                    Ok(()) => println!("Document '{}' updated.", key),
+                    Err(e) => println!("Failed to update document '{}': {}", key, e),
+                }
+            }
+            [cmd, key, assignment] if cmd == "PATCH" => {
+                let Some((field, value)) = assignment.split_once('=') else {
+                    println!("Usage: PATCH <key> <field>=<value>");
+                    continue;
+                };
+                let fields = Document { data: HashMap::from([(field.to_string(), parse_value(value))]) };
+                match neemo.patch(key, fields) {
+                    Ok(()) => println!("Field '{}' of document '{}' updated.", field, key),
+                    Err(e) => println!("Failed to patch document '{}': {}", key, e),
+                }
             }
             [cmd, key] if cmd == "GET" => {
                 if let Some(doc) = neemo.get(key) {
@@ -283,78 +318,216 @@ fn main() {
                     println!("Key '{}' not found.", key);
                 }
             }
+            [cmd, key] if cmd == "EXISTS" => println!("{}", neemo.contains_key(key)),
+            [cmd] if cmd == "COUNT" => match neemo.count() {
+                Ok(count) => println!("{} documents", count),
+                Err(e) => println!("Count failed: {}", e),
+            },
+            [cmd, field, value] if cmd == "COUNT" => match neemo.count_query(field, parse_value(value)) {
+                Ok(count) => println!("{} matched", count),
+                Err(e) => println!("Count failed: {}", e),
+            },
+            [cmd, field, value] if cmd == "EXPLAIN" => println!("{}", neemo.explain_query(field, parse_value(value))),
             [cmd, key] if cmd == "DELETE" => {
-                let key = key.to_string();
-                let neemo_clone = Arc::clone(&neemo);
-                thread::spawn(move || {
-                    if let Err(e) = neemo_clone.delete(&key) {
+                match neemo.delete(key) {
+                    Ok(()) => println!("Document '{}' deleted.", key),
+                    Err(e) => {
                         error!("Failed to delete document: {}", e);
+                        println!("Failed to delete document '{}': {}", key, e);
                     }
-                });
+                }
             }
-            [cmd, field, value] if cmd == "QUERY" => {
-                if let Ok(json_value) = serde_json::from_str(value) {
-                    let results = neemo.query(field, json_value);
-                    for doc in results {
-                        println!("{:?}", doc);
+            [cmd, key_a, key_b] if cmd == "SWAP" => {
+                match neemo.swap(key_a, key_b) {
+                    Ok(()) => println!("Swapped '{}' and '{}'.", key_a, key_b),
+                    Err(e) => {
+                        error!("Failed to swap documents: {}", e);
+                        println!("Failed to swap '{}' and '{}': {}", key_a, key_b, e);
                     }
                 }
             }
-            [cmd, field, start, end] if cmd == "RANGE" => {
-                if let Ok(start_value) = serde_json::from_str(start) {
-                    if let Ok(end_value) = serde_json::from_str(end) {
-                        let results = neemo.range_query(field, start_value, end_value);
-                        for doc in results {
+            [cmd, field, value] if cmd == "QUERY" => {
+                match neemo.query_detailed(field, parse_value(value)) {
+                    Ok(result) => {
+                        for (_, doc) in &result.documents {
                             println!("{:?}", doc);
                         }
+                        println!("({} matched, {} scanned in {:?})", result.matched, result.scanned, result.elapsed);
                     }
+                    Err(e) => println!("Query failed: {}", e),
+                }
+            }
+            [cmd, field, value, order, by, clause @ ..] if cmd == "QUERY" && order == "ORDER" && by == "BY" => {
+                match parse_order_by(&clause.join(" ")) {
+                    Some(sort_keys) => match neemo.query_sorted_multi(field, parse_value(value), &sort_keys) {
+                        Ok(results) => {
+                            for doc in results {
+                                println!("{:?}", doc);
+                            }
+                        }
+                        Err(e) => println!("Query failed: {}", e),
+                    },
+                    None => println!("Usage: QUERY <field> <value> ORDER BY <field> [ASC|DESC], ..."),
+                }
+            }
+            [cmd, field, op, operand] if cmd == "FIND" => match FindOp::parse(op) {
+                Some(op) => {
+                    let operand = parse_value(operand);
+                    let results = neemo.filter(|doc| {
+                        doc.get_path(field).is_some_and(|value| op.accepts(compare_find_operand(value, &operand)))
+                    });
+                    for doc in &results {
+                        println!("{:?}", doc);
+                    }
+                    println!("({} matched)", results.len());
+                }
+                None => println!("Usage: FIND <field> <=|!=|>|<|>=|<=> <value>"),
+            },
+            [cmd, field, start, end, options @ ..] if cmd == "RANGE" && options.len() <= 1 => {
+                let end_mode = match options {
+                    [] => Some(RangeEnd::Excluded),
+                    [mode] if mode == "INCLUSIVE" => Some(RangeEnd::Included),
+                    _ => None,
+                };
+                match end_mode {
+                    Some(end_mode) => match neemo.range_query_with(field, parse_value(start), parse_value(end), end_mode) {
+                        Ok(results) => {
+                            for doc in results {
+                                println!("{:?}", doc);
+                            }
+                        }
+                        Err(e) => println!("Range query failed: {}", e),
+                    },
+                    None => println!("Usage: RANGE <field> <start> <end> [INCLUSIVE]"),
                 }
             }
             [cmd, query] if cmd == "SEARCH" => {
-                let results = neemo.full_text_search(query);
-                for doc in results {
-                    println!("{:?}", doc);
+                print_search_results(neemo.full_text_search(query, Some(DEFAULT_SEARCH_LIMIT)));
+            }
+            [cmd, query, mode] if cmd == "SEARCH" && mode == "WHOLE" => {
+                print_search_results(neemo.full_text_search_with(query, Some(DEFAULT_SEARCH_LIMIT), TextMatch::WholeWord));
+            }
+            [cmd, query, limit, options @ ..] if cmd == "SEARCH" && options.len() <= 1 => {
+                let mode = match options {
+                    [] => Some(TextMatch::Substring),
+                    [mode] if mode == "WHOLE" => Some(TextMatch::WholeWord),
+                    _ => None,
+                };
+                match (limit.parse::<usize>(), mode) {
+                    (Ok(limit), Some(mode)) => print_search_results(neemo.full_text_search_with(query, Some(limit), mode)),
+                    (Err(_), _) => println!("Invalid search limit '{}'.", limit),
+                    (_, None) => println!("Usage: SEARCH <query> [limit] [WHOLE]"),
                 }
             }
-            [cmd, field, op] if cmd == "AGGREGATE" => {
-                if let Some(result) = neemo.aggregate(field, op) {
-                    println!("{:?}", result);
+            [cmd, field] if cmd == "FACET" => {
+                let facets = neemo.facet(field);
+                if facets.is_empty() {
+                    println!("No values found for field '{}'.", field);
+                } else {
+                    for (value, count) in facets {
+                        println!("{} ({})", value, count);
+                    }
+                }
+            }
+            [cmd, field] if cmd == "DISTINCT" => {
+                let values = neemo.distinct(field);
+                if values.is_empty() {
+                    println!("No values found for field '{}'.", field);
                 } else {
-                    println!("Invalid aggregation operation.");
+                    for value in values {
+                        println!("{}", value);
+                    }
+                }
+            }
+            [cmd, field, op] if cmd == "AGGREGATE" => {
+                let progress = |scanned| {
+                    println!("  ... {} documents scanned", scanned);
+                    true
+                };
+                match neemo.aggregate_with_progress(field, op, AGGREGATE_PROGRESS_INTERVAL, progress) {
+                    Ok(Some(result)) => println!("{:?}", result),
+                    Ok(None) if ["sum", "avg", "min", "max"].contains(&op.as_str()) => {
+                        println!("No numeric values found for field '{}'.", field)
+                    }
+                    Ok(None) => println!("Invalid aggregation operation."),
+                    Err(e) => println!("Aggregation failed: {}", e),
                 }
             }
             [cmd] if cmd == "BATCH" => {
+                // Example batch operation: insert multiple documents
+                let doc1 = Document { data: HashMap::from([("name".to_string(), Value::String("Alice".to_string()))]) };
+                let doc2 = Document { data: HashMap::from([("name".to_string(), Value::String("Bob".to_string()))]) };
+                let ops = [
+                    BatchOp::Insert { key: "doc1".to_string(), doc: doc1 },
+                    BatchOp::Insert { key: "doc2".to_string(), doc: doc2 },
+                ];
+                match neemo.batch(&ops) {
+                    Ok(()) => println!("Batch of {} operations applied.", ops.len()),
+                    Err(e) => {
+                        error!("Batch operation failed: {}", e);
+                        println!("Batch operation failed: {}", e);
+                    }
+                }
+            }
+            [cmd, path] if cmd == "EXPORT" => {
+                let path = path.to_string();
                 let neemo_clone = Arc::clone(&neemo);
                 thread::spawn(move || {
-                    neemo_clone.batch(|db, _index| {
-                        // Example batch operation: insert multiple documents
-                        let doc1 = Document { data: HashMap::from([("name".to_string(), Value::String("Alice".to_string()))]) };
-                        let doc2 = Document { data: HashMap::from([("name".to_string(), Value::String("Bob".to_string()))]) };
-                        db.insert("doc1".as_bytes(), serde_json::to_string(&doc1).unwrap().as_bytes()).unwrap();
-                        db.insert("doc2".as_bytes(), serde_json::to_string(&doc2).unwrap().as_bytes()).unwrap();
-                    });
+                    if let Err(e) = neemo_clone.export(&path) {
+                        error!("Failed to export data: {}", e);
+                    } else {
+                        println!("Data exported successfully.");
+                    }
                 });
-                println!("Batch operation started.");
             }
-            [cmd, path] if cmd == "EXPORT" => {
+            [cmd, path, format] if cmd == "EXPORT" && format == "CSV" => {
                 let path = path.to_string();
                 let neemo_clone = Arc::clone(&neemo);
                 thread::spawn(move || {
-                    if let Err(e) = neemo_clone.export(&path) {
+                    if let Err(e) = neemo_clone.export_csv(&path) {
                         error!("Failed to export data: {}", e);
                     } else {
                         println!("Data exported successfully.");
                     }
                 });
             }
-            [cmd, path] if cmd == "IMPORT" => {
+            [cmd, path, format] if cmd == "IMPORT" && format == "CSV" => {
+                let path = path.to_string();
+                let neemo_clone = Arc::clone(&neemo);
+                thread::spawn(move || {
+                    match neemo_clone.import_csv(&path) {
+                        Ok(imported) => println!("Imported {} documents.", imported),
+                        Err(e) => error!("Failed to import data: {}", e),
+                    }
+                });
+            }
+            [cmd, path, flag] if cmd == "EXPORT" && flag == "DEDUPE" => {
+                let path = path.to_string();
+                let neemo_clone = Arc::clone(&neemo);
+                thread::spawn(move || {
+                    match neemo_clone.compact_export(&path, true) {
+                        Ok(duplicates) => println!("Data exported successfully ({} duplicates dropped).", duplicates),
+                        Err(e) => error!("Failed to export data: {}", e),
+                    }
+                });
+            }
+            [cmd, path, rest @ ..] if cmd == "IMPORT" && rest.len() <= 1 => {
                 let path = path.to_string();
+                let Ok(resume_after) = rest.first().map_or(Ok(0), |line| line.parse::<usize>()) else {
+                    println!("Usage: IMPORT <path> [resume_after_line]");
+                    continue;
+                };
                 let neemo_clone = Arc::clone(&neemo);
                 thread::spawn(move || {
-                    if let Err(e) = neemo_clone.import(&path) {
+                    let report = neemo_clone.import_batched(&path, DEFAULT_IMPORT_BATCH_SIZE, resume_after);
+                    if let Some(e) = &report.error {
                         error!("Failed to import data: {}", e);
+                        println!(
+                            "Import stopped after line {} ({} documents imported): {}. Resume with IMPORT {} {}",
+                            report.last_committed_line, report.imported, e, path, report.last_committed_line
+                        );
                     } else {
-                        println!("Data imported successfully.");
+                        println!("Imported {} documents ({} lines skipped).", report.imported, report.skipped);
                     }
                 });
             }
@@ -380,6 +553,13 @@ fn main() {
                     }
                 });
             }
+            [cmd] if cmd == "CHECKPOINT" => {
+                let before = neemo.flush().and_then(|_| neemo.size_on_disk());
+                match neemo.checkpoint().and_then(|_| Ok((before?, neemo.size_on_disk()?))) {
+                    Ok((before, after)) => println!("Checkpoint complete: {} bytes -> {} bytes on disk.", before, after),
+                    Err(e) => println!("Checkpoint failed: {}", e),
+                }
+            }
             [cmd] if cmd == "LIST" => {
                 let results = neemo.list();
                 if results.is_empty() {
@@ -390,29 +570,198 @@ fn main() {
                     }
                 }
             }
+            [cmd, order, by, field, direction @ ..] if cmd == "LIST" && order == "ORDER" && by == "BY" && direction.len() <= 1 => {
+                let ascending = match direction {
+                    [] => Some(true),
+                    [dir] if dir.eq_ignore_ascii_case("ASC") => Some(true),
+                    [dir] if dir.eq_ignore_ascii_case("DESC") => Some(false),
+                    _ => None,
+                };
+                match ascending {
+                    Some(ascending) => {
+                        for (i, doc) in neemo.list_sorted(field, ascending).iter().enumerate() {
+                            println!("Document {}: {:?}", i + 1, doc);
+                        }
+                    }
+                    None => println!("Usage: LIST ORDER BY <field> [ASC|DESC]"),
+                }
+            }
+            #[cfg(all(unix, feature = "uds"))]
+            [cmd, path] if cmd == "SERVE" => {
+                if let Some(server) = socket_server.take() {
+                    server.stop();
+                }
+                match uds::UdsServer::start(Arc::clone(&neemo), path) {
+                    Ok(server) => {
+                        println!("Serving on socket '{}'.", path);
+                        socket_server = Some(server);
+                    }
+                    Err(e) => println!("Failed to start socket server: {}", e),
+                }
+            }
             [cmd] if cmd == "EXIT" || cmd == "QUIT" => {
                 println!("Exiting Neemo...");
                 break;
             }
             _ => {
                 println!("Invalid command. Available commands:");
-                println!("  CREATE DATABASE <name>    - Create a new database");
+                println!("  CREATE DATABASE <name>    - Create a new database and switch to it");
                 println!("  USE DATABASE <name>       - Switch to a database");
+                println!("  USE DATABASE DEFAULT      - Switch back to the startup database");
+                println!("  CREATE INDEX <field>     - Index a field or dotted path");
+                println!("  DROP INDEX <field>       - Remove a field's index");
+                println!("  INDEXES                  - List indexed fields");
                 println!("  INSERT <key>             - Insert a new document");
+                println!("  UPDATE <key>             - Change fields of a document interactively");
+                println!("  PATCH <key> <field>=<value> - Set one field of a document");
                 println!("  GET <key>                - Retrieve a document");
                 println!("  DELETE <key>             - Delete a document");
+                println!("  EXISTS <key>             - Check whether a document exists");
+                println!("  COUNT                    - Count all documents");
+                println!("  COUNT <field> <value>    - Count documents matching a field");
+                println!("  EXPLAIN <field> <value>  - Show whether a query uses an index or a full scan");
+                println!("  SWAP <key> <key>         - Exchange two documents");
                 println!("  QUERY <field> <value>    - Query documents by field");
-                println!("  RANGE <field> <start> <end> - Range query");
-                println!("  SEARCH <query>           - Full-text search");
+                println!("  QUERY <field> <value> ORDER BY <field> [ASC|DESC], ... - Sorted query");
+                println!("  FIND <field> <op> <value> - Find documents comparing a field with =, !=, >, <, >= or <=");
+                println!("      e.g. FIND age >= 30, FIND name != \"Bob\", FIND city = London");
+                println!("  RANGE <field> <start> <end> [INCLUSIVE] - Range query, end excluded unless INCLUSIVE");
+                println!("  SEARCH <query> [limit] [WHOLE] - Full-text search");
+                println!("  FACET <field>            - Count documents per field value");
+                println!("  DISTINCT <field>         - List the distinct values of a field");
                 println!("  AGGREGATE <field> <op>   - Aggregate operation");
                 println!("  BATCH                    - Run batch operation");
-                println!("  EXPORT <path>            - Export database");
-                println!("  IMPORT <path>            - Import database");
+                println!("  EXPORT <path> [DEDUPE]   - Export database, optionally dropping duplicates");
+                println!("  IMPORT <path> [line]     - Import database, resuming after a line");
+                println!("  EXPORT <path> CSV        - Export documents as CSV");
+                println!("  IMPORT <path> CSV        - Import documents from CSV");
                 println!("  BACKUP <path>            - Backup database");
                 println!("  RESTORE <path>           - Restore database");
+                println!("  CHECKPOINT               - Reclaim disk space freed by deletes");
                 println!("  LIST                     - List all documents");
+                println!("  LIST ORDER BY <field> [ASC|DESC] - List all documents sorted by a field");
+                #[cfg(all(unix, feature = "uds"))]
+                println!("  SERVE <socket>           - Share this database over a Unix socket");
                 println!("  EXIT/QUIT                - Exit the program");
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncation_notice_reports_shown_matches() {
+        let doc = Document { data: HashMap::new() };
+        let truncated = SearchResults { documents: vec![doc.clone(); 5], truncated: true };
+        assert_eq!(truncation_notice(&truncated).as_deref(), Some("(showing 5 of 6+ matches)"));
+
+        let complete = SearchResults { documents: vec![doc; 3], truncated: false };
+        assert_eq!(truncation_notice(&complete), None);
+    }
+
+    #[test]
+    fn parse_order_by_reads_directions() {
+        let keys = parse_order_by("region ASC, score desc, name").unwrap();
+        let expected = vec![("region".to_string(), false), ("score".to_string(), true), ("name".to_string(), false)];
+        assert_eq!(keys, expected);
+        assert_eq!(parse_order_by("score SIDEWAYS"), None);
+        assert_eq!(parse_order_by("a,"), None);
+    }
+
+    #[test]
+    fn find_operators_parse_and_compare() {
+        let ops: Vec<_> = ["=", "!=", ">", "<", ">=", "<="].iter().map(|token| FindOp::parse(token)).collect();
+        let expected = [FindOp::Eq, FindOp::Ne, FindOp::Gt, FindOp::Lt, FindOp::Ge, FindOp::Le];
+        assert_eq!(ops, expected.map(Some));
+        assert_eq!(FindOp::parse("=="), None);
+        assert_eq!(FindOp::parse("=>"), None);
+
+        let accepted = |op: FindOp| [Ordering::Less, Ordering::Equal, Ordering::Greater].map(|ordering| op.accepts(ordering));
+        assert_eq!(accepted(FindOp::Eq), [false, true, false]);
+        assert_eq!(accepted(FindOp::Ne), [true, false, true]);
+        assert_eq!(accepted(FindOp::Gt), [false, false, true]);
+        assert_eq!(accepted(FindOp::Lt), [true, false, false]);
+        assert_eq!(accepted(FindOp::Ge), [false, true, true]);
+        assert_eq!(accepted(FindOp::Le), [true, true, false]);
+    }
+
+    #[test]
+    fn find_operands_compare_numerically_or_as_text() {
+        // Numbers compare by value, not by their digits.
+        assert_eq!(compare_find_operand(&parse_value("10"), &parse_value("9")), Ordering::Greater);
+        assert_eq!(compare_find_operand(&parse_value("2.0"), &parse_value("2")), Ordering::Equal);
+        assert_eq!(compare_find_operand(&parse_value("\"apple\""), &parse_value("banana")), Ordering::Less);
+        // A number against a string falls back to comparing text.
+        assert_eq!(compare_find_operand(&Value::String("42".to_string()), &parse_value("42")), Ordering::Equal);
+        assert_eq!(compare_find_operand(&Value::String("10".to_string()), &parse_value("9")), Ordering::Less);
+        assert_eq!(compare_find_operand(&Value::Bool(true), &parse_value("true")), Ordering::Equal);
+    }
+
+    #[test]
+    fn find_commands_tokenize_into_field_operator_and_value() {
+        assert_eq!(tokenize("FIND age >= 30"), ["FIND", "age", ">=", "30"]);
+        assert_eq!(tokenize("FIND name != \"Ann Lee\""), ["FIND", "name", "!=", "Ann Lee"]);
+    }
+
+    #[test]
+    fn tokenize_groups_quoted_text() {
+        assert_eq!(tokenize("SEARCH \"hello world\""), ["SEARCH", "hello world"]);
+        assert_eq!(tokenize("INSERT \"my key\""), ["INSERT", "my key"]);
+        assert_eq!(tokenize("GET pre\"fix and\"post"), ["GET", "prefix andpost"]);
+        assert_eq!(tokenize("INSERT \"\""), ["INSERT", ""]);
+    }
+
+    #[test]
+    fn tokenize_handles_escapes_and_spacing() {
+        assert_eq!(tokenize(r#"SEARCH "say \"hi\"""#), ["SEARCH", "say \"hi\""]);
+        assert_eq!(tokenize(r"GET back\\slash"), ["GET", "back\\slash"]);
+        assert_eq!(tokenize(r"GET two\ words"), ["GET", "two words"]);
+        assert_eq!(tokenize("  GET   key  \t "), ["GET", "key"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn tokenize_tolerates_unfinished_quotes_and_escapes() {
+        assert_eq!(tokenize("SEARCH \"hello world  "), ["SEARCH", "hello world  "]);
+        assert_eq!(tokenize("GET key\\"), ["GET", "key"]);
+    }
+
+    #[test]
+    fn count_and_exists_arguments_parse() {
+        assert_eq!(tokenize("COUNT"), ["COUNT"]);
+        let count = tokenize("COUNT age 30");
+        assert_eq!(count, ["COUNT", "age", "30"]);
+        assert_eq!(parse_value(&count[2]), Value::from(30));
+        let count = tokenize("COUNT status \"on hold\"");
+        assert_eq!(count, ["COUNT", "status", "on hold"]);
+        assert_eq!(parse_value(&count[2]), Value::from("on hold"));
+        assert_eq!(parse_value("true"), Value::Bool(true));
+
+        assert_eq!(tokenize("EXISTS \"user 1\""), ["EXISTS", "user 1"]);
+    }
+
+    #[test]
+    fn validate_db_name_accepts_plain_nemo_names() {
+        assert_eq!(validate_db_name("shop.nemo"), Ok(()));
+        assert_eq!(validate_db_name("my-db_2.nemo"), Ok(()));
+        assert_eq!(validate_db_name("v1.2.nemo"), Ok(()));
+    }
+
+    #[test]
+    fn validate_db_name_requires_the_nemo_extension() {
+        for name in ["shop", "shop.db", "shop.nemo.bak", "shop.NEMO"] {
+            assert_eq!(validate_db_name(name), Err("Database name must end with '.nemo'".to_string()), "{name}");
+        }
+        assert!(validate_db_name(".nemo").is_err());
+    }
+
+    #[test]
+    fn validate_db_name_rejects_path_traversal() {
+        for name in ["../shop.nemo", "..\\shop.nemo", "a/b.nemo", "/etc/shop.nemo", "a\\b.nemo", "...nemo"] {
+            assert_eq!(validate_db_name(name), Err(format!("Invalid database name '{}'", name)), "{name}");
+        }
+    }
+}
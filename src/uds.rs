@@ -0,0 +1,232 @@
+//! Unix domain socket access to a shared Neemo instance.
+//!
+//! Messages are framed as a 4-byte big-endian length followed by that many
+//! bytes of JSON. Each [`Request`] frame is answered by one [`Response`].
+
+use crate::{lock, Document, KvStore, Neemo, NeemoError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use log::error;
+
+/// Largest frame accepted from a peer.
+const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// An operation sent to the server.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Insert { key: String, doc: Document },
+    Get { key: String },
+    Delete { key: String },
+    Query { field: String, value: Value },
+}
+
+/// The server's reply to a [`Request`].
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok(Value),
+    Error(String),
+}
+
+fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Reads one frame, returning `None` if the peer closed the connection.
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+fn handle_request<S: KvStore>(neemo: &Neemo<S>, request: Request) -> Response {
+    let result = match request {
+        Request::Insert { key, doc } => neemo.insert(&key, doc).map(|_| Value::Null),
+        Request::Get { key } => serde_json::to_value(neemo.get(&key)).map_err(NeemoError::from),
+        Request::Delete { key } => neemo.delete(&key).map(|_| Value::Null),
+        Request::Query { field, value } => neemo.query(&field, value).and_then(|docs| Ok(serde_json::to_value(docs)?)),
+    };
+    match result {
+        Ok(value) => Response::Ok(value),
+        Err(e) => Response::Error(e.to_string()),
+    }
+}
+
+fn serve_connection<S: KvStore>(neemo: &Neemo<S>, mut stream: UnixStream) -> io::Result<()> {
+    while let Some(payload) = read_frame(&mut stream)? {
+        let response = match serde_json::from_slice(&payload) {
+            Ok(request) => handle_request(neemo, request),
+            Err(e) => Response::Error(format!("invalid request: {}", e)),
+        };
+        write_frame(&mut stream, &serde_json::to_vec(&response)?)?;
+    }
+    Ok(())
+}
+
+/// A connection being served, kept so the server can close it on stop.
+struct Connection {
+    stream: UnixStream,
+    worker: JoinHandle<()>,
+}
+
+/// Serves a Neemo instance on a Unix domain socket until stopped.
+///
+/// The socket file is removed by [`UdsServer::stop`] or when the server is
+/// dropped.
+pub struct UdsServer {
+    path: PathBuf,
+    shutdown: Arc<AtomicBool>,
+    acceptor: Mutex<Option<JoinHandle<()>>>,
+    connections: Arc<Mutex<Vec<Connection>>>,
+}
+
+impl UdsServer {
+    /// Binds `path` and starts accepting connections on a background thread.
+    /// A socket file left at `path` by a server that is no longer running is
+    /// replaced; one with a live server behind it fails with `AddrInUse`.
+    pub fn start<S: KvStore + 'static>(neemo: Arc<Neemo<S>>, path: impl AsRef<Path>) -> io::Result<UdsServer> {
+        let path = path.as_ref().to_path_buf();
+        remove_stale_socket(&path)?;
+        let listener = UnixListener::bind(&path)?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let connections: Arc<Mutex<Vec<Connection>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let stop_flag = Arc::clone(&shutdown);
+        let served = Arc::clone(&connections);
+        let acceptor = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop_flag.load(Ordering::SeqCst) {
+                    break;
+                }
+                let connection = match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                    Ok((handle, stream)) => {
+                        let neemo = Arc::clone(&neemo);
+                        let worker = thread::spawn(move || {
+                            if let Err(e) = serve_connection(&neemo, stream) {
+                                error!("Socket connection failed: {}", e);
+                            }
+                        });
+                        Connection { stream: handle, worker }
+                    }
+                    Err(e) => {
+                        error!("Failed to accept socket connection: {}", e);
+                        continue;
+                    }
+                };
+                let mut served = lock(&served);
+                served.retain(|connection| !connection.worker.is_finished());
+                served.push(connection);
+            }
+        });
+
+        Ok(UdsServer { path, shutdown, acceptor: Mutex::new(Some(acceptor)), connections })
+    }
+
+    /// Returns the socket path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops accepting connections, closes the open ones and removes the
+    /// socket file.
+    pub fn stop(&self) {
+        let Some(acceptor) = lock(&self.acceptor).take() else {
+            return;
+        };
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the blocked accept so the thread sees the shutdown flag.
+        let _ = UnixStream::connect(&self.path);
+        let _ = acceptor.join();
+        let connections: Vec<Connection> = lock(&self.connections).drain(..).collect();
+        for connection in connections {
+            let _ = connection.stream.shutdown(Shutdown::Both);
+            let _ = connection.worker.join();
+        }
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            error!("Failed to remove socket {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Removes a socket file at `path` that no server is listening on.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() && UnixStream::connect(path).is_err() => std::fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+impl Drop for UdsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Client for a [`UdsServer`].
+pub struct UdsClient {
+    stream: UnixStream,
+}
+
+impl UdsClient {
+    /// Connects to the server listening on `path`.
+    pub fn connect(path: impl AsRef<Path>) -> Result<UdsClient, NeemoError> {
+        Ok(UdsClient { stream: UnixStream::connect(path)? })
+    }
+
+    fn call(&mut self, request: &Request) -> Result<Value, NeemoError> {
+        let payload = serde_json::to_vec(request)?;
+        write_frame(&mut self.stream, &payload)?;
+        let reply = read_frame(&mut self.stream)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection"))?;
+        match serde_json::from_slice(&reply)? {
+            Response::Ok(value) => Ok(value),
+            Response::Error(message) => Err(NeemoError::Remote(message)),
+        }
+    }
+
+    /// Inserts or updates a document.
+    pub fn insert(&mut self, key: &str, doc: Document) -> Result<(), NeemoError> {
+        self.call(&Request::Insert { key: key.to_string(), doc }).map(|_| ())
+    }
+
+    /// Retrieves a document by key.
+    pub fn get(&mut self, key: &str) -> Result<Option<Document>, NeemoError> {
+        let value = self.call(&Request::Get { key: key.to_string() })?;
+        serde_json::from_value(value).map_err(NeemoError::from)
+    }
+
+    /// Deletes a document by key.
+    pub fn delete(&mut self, key: &str) -> Result<(), NeemoError> {
+        self.call(&Request::Delete { key: key.to_string() }).map(|_| ())
+    }
+
+    /// Queries documents based on a field-value pair.
+    pub fn query(&mut self, field: &str, value: Value) -> Result<Vec<Document>, NeemoError> {
+        let value = self.call(&Request::Query { field: field.to_string(), value })?;
+        serde_json::from_value(value).map_err(NeemoError::from)
+    }
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use common::doc;
+use neemo::{Neemo, NeemoError};
+use serde_json::json;
+
+fn status_counts() -> Neemo<neemo::MemoryStore> {
+    let neemo = Neemo::in_memory();
+    let statuses = ["active", "inactive", "active", "pending", "active", "inactive"];
+    for (i, status) in statuses.iter().enumerate() {
+        neemo.insert(&format!("user{}", i), doc(json!({ "status": status }))).unwrap();
+    }
+    neemo.insert("no-status", doc(json!({ "name": "x" }))).unwrap();
+    neemo
+}
+
+#[test]
+fn facet_counts_values_most_common_first() {
+    let neemo = status_counts();
+    let expected = vec![(json!("active"), 3), (json!("inactive"), 2), (json!("pending"), 1)];
+    assert_eq!(neemo.facet("status"), expected);
+
+    neemo.create_index("status").unwrap();
+    assert_eq!(neemo.facet("status"), expected);
+}
+
+#[test]
+fn facet_of_missing_field_is_empty() {
+    let neemo = status_counts();
+    assert!(neemo.facet("missing").is_empty());
+}
+
+#[test]
+fn count_by_prefix_and_child_prefixes_follow_key_hierarchy() {
+    let neemo = Neemo::in_memory();
+    for key in ["org:1:user:a", "org:1:user:b", "org:2:user:c", "other"] {
+        neemo.insert(key, doc(json!({ "key": key }))).unwrap();
+    }
+
+    assert_eq!(neemo.count_by_prefix("org:1:"), 2);
+    assert_eq!(neemo.count_by_prefix("org:"), 3);
+    assert_eq!(neemo.count_by_prefix("missing:"), 0);
+    assert_eq!(neemo.child_prefixes("org:", ':'), vec![("1".to_string(), 2), ("2".to_string(), 1)]);
+    assert_eq!(neemo.child_prefixes("org:1:user:", ':'), vec![("a".to_string(), 1), ("b".to_string(), 1)]);
+}
+
+#[test]
+fn aggregate_reports_monotonic_progress_and_can_be_cancelled() {
+    let neemo = Neemo::in_memory();
+    for i in 0..3000 {
+        neemo.insert(&format!("doc{:04}", i), doc(json!({ "n": 1 }))).unwrap();
+    }
+
+    let mut counts = Vec::new();
+    let total = neemo.aggregate_with_progress("n", "sum", 500, |scanned| {
+        counts.push(scanned);
+        true
+    });
+    assert_eq!(total.unwrap(), Some(json!(3000.0)));
+    assert_eq!(counts, vec![500, 1000, 1500, 2000, 2500]);
+    assert!(counts.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let cancelled = neemo.aggregate_with_progress("n", "sum", 500, |scanned| scanned < 1000);
+    assert!(matches!(cancelled, Err(NeemoError::Cancelled)));
+}
+
+#[test]
+fn fold_sums_name_lengths() {
+    let neemo = Neemo::in_memory();
+    let names = ["Ann", "Bartholomew", "Cy", "Dolores"];
+    for name in names {
+        neemo.insert(name, doc(json!({ "name": name }))).unwrap();
+    }
+    neemo.insert("nameless", doc(json!({ "age": 3 }))).unwrap();
+
+    let total = neemo.fold(0, |total, doc| total + doc.data.get("name").and_then(|n| n.as_str()).map_or(0, str::len));
+    assert_eq!(total, names.iter().map(|name| name.len()).sum::<usize>());
+}
+
+#[test]
+fn min_and_max_cover_integers_and_floats() {
+    let neemo = Neemo::in_memory();
+    for (key, price) in [("a", json!(3)), ("b", json!(-1.5)), ("c", json!(7)), ("d", json!(2.25)), ("e", json!("free"))] {
+        neemo.insert(key, doc(json!({ "price": price }))).unwrap();
+    }
+
+    assert_eq!(neemo.aggregate("price", "min").unwrap(), Some(json!(-1.5)));
+    assert_eq!(neemo.aggregate("price", "max").unwrap(), Some(json!(7.0)));
+    assert_eq!(neemo.aggregate("missing", "min").unwrap(), None);
+    assert_eq!(neemo.aggregate("missing", "max").unwrap(), None);
+}
+
+#[test]
+fn aggregates_without_numbers_are_none() {
+    let empty = Neemo::in_memory();
+    for op in ["sum", "avg", "min", "max"] {
+        assert_eq!(empty.aggregate("amount", op).unwrap(), None, "{} over an empty database", op);
+    }
+    assert_eq!(empty.aggregate("amount", "count").unwrap(), Some(json!(0)));
+
+    let neemo = Neemo::in_memory();
+    neemo.insert("a", doc(json!({ "other": 1 }))).unwrap();
+    for op in ["sum", "avg", "min", "max"] {
+        assert_eq!(neemo.aggregate("amount", op).unwrap(), None, "{} over a missing field", op);
+    }
+    assert_eq!(neemo.aggregate("amount", "count").unwrap(), Some(json!(0)));
+    assert_eq!(neemo.aggregate("other", "median").unwrap(), None);
+}
+
+#[test]
+fn aggregate_grouped_buckets_by_region() {
+    let neemo = Neemo::in_memory();
+    let sales = [
+        ("s1", json!({ "region": "north", "amount": 10 })),
+        ("s2", json!({ "region": "north", "amount": 5.5 })),
+        ("s3", json!({ "region": "south", "amount": 7 })),
+        ("s4", json!({ "region": "south", "amount": "n/a" })),
+        ("s5", json!({ "region": "east" })),
+        ("s6", json!({ "amount": 100 })),
+    ];
+    for (key, value) in sales {
+        neemo.insert(key, doc(value)).unwrap();
+    }
+
+    let sums = neemo.aggregate_grouped("region", "amount", "sum");
+    assert_eq!(sums.len(), 2);
+    assert_eq!(sums["north"], json!(15.5));
+    assert_eq!(sums["south"], json!(7.0));
+
+    let counts = neemo.aggregate_grouped("region", "amount", "count");
+    assert_eq!(counts.len(), 3);
+    assert_eq!((counts["north"].clone(), counts["south"].clone(), counts["east"].clone()), (json!(2), json!(1), json!(0)));
+}
+
+#[test]
+fn fold_skips_corrupt_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap());
+    neemo.insert("a", doc(json!({ "n": 2 }))).unwrap();
+    docs.insert("b", b"not json".to_vec()).unwrap();
+    neemo.insert("c", doc(json!({ "n": 5 }))).unwrap();
+
+    assert_eq!(neemo.fold(0, |total, doc| total + doc.data["n"].as_i64().unwrap()), 7);
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use std::sync::Arc;
+
+use common::{doc, open_temp, values};
+use neemo::{FieldType, Neemo, NeemoError, Schema};
+use serde_json::json;
+
+#[tokio::test]
+async fn async_wrappers_round_trip_a_document() {
+    let (_dir, neemo) = open_temp();
+    let neemo = Arc::new(neemo);
+    neemo.create_index("city").unwrap();
+
+    neemo.insert_async("a", doc(json!({ "name": "Ann", "city": "NYC" }))).await.unwrap();
+    neemo.insert_async("b", doc(json!({ "name": "Bo", "city": "LA" }))).await.unwrap();
+    assert_eq!(neemo.get_async("a").await.unwrap().unwrap().data["name"], json!("Ann"));
+    assert_eq!(values(&neemo.query_async("city", json!("LA")).await.unwrap(), "name"), vec![json!("Bo")]);
+
+    neemo.delete_async("a").await.unwrap();
+    assert!(neemo.get_async("a").await.unwrap().is_none());
+    assert!(neemo.query_async("city", json!("NYC")).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn async_wrappers_report_errors() {
+    let neemo = Arc::new(Neemo::in_memory());
+    neemo.set_schema(Schema::new("user:").require("age", FieldType::Number));
+
+    let invalid = neemo.insert_async("user:a", doc(json!({ "age": "old" }))).await;
+    assert!(matches!(invalid, Err(NeemoError::Validation(_))));
+    assert!(neemo.get_async("user:a").await.unwrap().is_none());
+}
@@ -0,0 +1,49 @@
+//! Runs the same operations against the sled and in-memory backends and
+//! checks that they observe the same results.
+
+mod common;
+
+use common::{doc, open_temp, values};
+use neemo::{KvStore, Neemo};
+use serde_json::{json, Value};
+
+/// Applies a fixed sequence of operations and records what each read sees.
+fn exercise<S: KvStore>(neemo: &Neemo<S>) -> Vec<Value> {
+    let mut seen = Vec::new();
+    neemo.create_index("city").unwrap();
+    neemo.create_index("age").unwrap();
+
+    neemo.insert("user:1", doc(json!({ "name": "Ann", "city": "Oslo", "age": 31, "bio": "likes rust" }))).unwrap();
+    neemo.insert("user:2", doc(json!({ "name": "Bo", "city": "Rome", "age": 25, "bio": "likes pasta" }))).unwrap();
+    neemo.insert("user:3", doc(json!({ "name": "Cy", "city": "Oslo", "age": 47, "bio": "hates rain" }))).unwrap();
+    neemo.insert("team:1", doc(json!({ "name": "core", "size": 3 }))).unwrap();
+    seen.push(json!(neemo.get("user:2").map(|d| d.data["name"].clone())));
+
+    neemo.update("user:2", doc(json!({ "name": "Bo", "city": "Oslo", "age": 26, "bio": "likes pasta" }))).unwrap();
+    neemo.patch("user:3", doc(json!({ "age": 48 }))).unwrap();
+    neemo.delete("team:1").unwrap();
+    seen.push(json!(neemo.get("team:1").is_none()));
+
+    seen.push(json!(values(&neemo.query("city", json!("Oslo")).unwrap(), "name")));
+    seen.push(json!(values(&neemo.query("name", json!("Cy")).unwrap(), "age")));
+    seen.push(json!(values(&neemo.range_query("age", json!(26), json!(40)).unwrap(), "name")));
+    seen.push(json!(values(&neemo.query_gt("age", json!(30)), "name")));
+    seen.push(json!(values(&neemo.list(), "name")));
+    seen.push(json!(neemo.get_prefix("user:").into_iter().map(|(key, _)| key).collect::<Vec<_>>()));
+    seen.push(json!(neemo.count().unwrap()));
+    seen.push(json!(neemo.facet("city")));
+    seen.push(json!(values(&neemo.full_text_search("likes", None).unwrap().documents, "name")));
+    seen
+}
+
+#[test]
+fn sled_and_memory_backends_agree() {
+    let (_dir, on_disk) = open_temp();
+    let in_memory = Neemo::in_memory();
+
+    let expected = exercise(&on_disk);
+    assert_eq!(exercise(&in_memory), expected);
+    assert_eq!(expected[0], json!("Bo"));
+    assert_eq!(expected[2], json!(["Ann", "Cy", "Bo"]));
+    assert_eq!(expected[8], json!(3));
+}
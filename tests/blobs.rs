@@ -0,0 +1,37 @@
+mod common;
+
+use common::{db_path, doc};
+use neemo::{MemoryStore, Neemo, NeemoError};
+use serde_json::json;
+
+#[test]
+fn a_one_megabyte_blob_round_trips_exactly() {
+    let dir = tempfile::tempdir().unwrap();
+    let bytes: Vec<u8> = (0..1024 * 1024).map(|i: u32| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    {
+        let neemo = Neemo::open(&db_path(&dir)).unwrap();
+        neemo.insert("photo", doc(json!({ "title": "sunset" }))).unwrap();
+        neemo.insert_blob("photo", "raw", &bytes).unwrap();
+        neemo.insert_blob("photo", "thumb", &bytes[..16]).unwrap();
+        // The document itself stays small and free of the bytes.
+        assert_eq!(neemo.get("photo").unwrap().data.len(), 1);
+    }
+
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    assert_eq!(neemo.get_blob("photo", "raw").unwrap().unwrap(), bytes);
+    assert_eq!(neemo.get_blob("photo", "thumb").unwrap().unwrap(), &bytes[..16]);
+    assert!(neemo.get_blob("photo", "missing").unwrap().is_none());
+
+    neemo.delete("photo").unwrap();
+    assert!(neemo.get_blob("photo", "raw").unwrap().is_none());
+}
+
+#[test]
+fn blobs_need_a_document_and_a_blob_store() {
+    let neemo = Neemo::in_memory();
+    assert!(matches!(neemo.insert_blob("missing", "raw", b"x"), Err(NeemoError::NotFound(_))));
+
+    let bare = Neemo::with_stores(MemoryStore::new(), MemoryStore::new());
+    bare.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    assert!(matches!(bare.insert_blob("a", "raw", b"x"), Err(NeemoError::BlobsUnsupported)));
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+use common::{doc, ProbeStore};
+use neemo::Neemo;
+use serde_json::json;
+
+#[test]
+fn repeated_get_is_served_from_cache_until_the_key_changes() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0).with_document_cache(8);
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    let reads = || probe.gets.load(Ordering::SeqCst);
+
+    let before = reads();
+    assert_eq!(neemo.get("a").unwrap().data["n"], json!(1));
+    let after_fill = reads();
+    assert_eq!(after_fill, before + 1);
+    assert_eq!(neemo.get("a").unwrap().data["n"], json!(1));
+    assert_eq!(reads(), after_fill);
+
+    neemo.update("a", doc(json!({ "n": 2 }))).unwrap();
+    let after_update = reads();
+    assert_eq!(neemo.get("a").unwrap().data["n"], json!(2));
+    assert_eq!(reads(), after_update + 1);
+
+    neemo.delete("a").unwrap();
+    assert!(neemo.get("a").is_none());
+}
+
+#[test]
+fn a_write_during_a_cache_fill_is_not_hidden() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0).with_document_cache(8);
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+
+    let (go, started) = mpsc::channel();
+    let (finished, done) = mpsc::channel();
+    *probe.after_get.lock().unwrap() = Some(Box::new(move || {
+        go.send(()).unwrap();
+        done.recv().unwrap();
+    }));
+    thread::scope(|scope| {
+        let neemo = &neemo;
+        scope.spawn(move || {
+            started.recv().unwrap();
+            neemo.update("a", doc(json!({ "n": 2 }))).unwrap();
+            finished.send(()).unwrap();
+        });
+        // This read takes `n: 1` from storage, and the update lands before
+        // it would be cached.
+        assert_eq!(neemo.get("a").unwrap().data["n"], json!(1));
+    });
+    assert_eq!(neemo.get("a").unwrap().data["n"], json!(2));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{db_path, doc, values};
+use neemo::Neemo;
+use serde_json::json;
+
+#[test]
+fn collections_keep_keys_and_indexes_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let neemo = Neemo::open(&db_path(&dir)).unwrap();
+        let users = neemo.collection("users").unwrap();
+        let orders = neemo.collection("orders").unwrap();
+        users.create_index("status").unwrap();
+
+        users.insert("1", doc(json!({ "name": "Ann", "status": "active" }))).unwrap();
+        orders.insert("1", doc(json!({ "item": "lamp", "status": "active" }))).unwrap();
+        neemo.insert("1", doc(json!({ "note": "default" }))).unwrap();
+
+        assert_eq!(users.get("1").unwrap().data["name"], json!("Ann"));
+        assert_eq!(orders.get("1").unwrap().data["item"], json!("lamp"));
+        assert_eq!(values(&users.query("status", json!("active")).unwrap(), "name"), vec![json!("Ann")]);
+        assert_eq!(values(&orders.query("status", json!("active")).unwrap(), "item"), vec![json!("lamp")]);
+        assert_eq!(users.list_indexes(), vec!["status"]);
+        assert!(orders.list_indexes().is_empty());
+        assert_eq!(neemo.list().len(), 1);
+
+        orders.delete("1").unwrap();
+        assert!(users.get("1").is_some());
+        assert!(neemo.get("1").is_some());
+    }
+
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    let mut names = neemo.collection_names();
+    names.sort();
+    assert_eq!(names, vec!["orders", "users"]);
+    let users = neemo.collection("users").unwrap();
+    assert!(Arc::ptr_eq(&users, &neemo.collection("users").unwrap()));
+    assert_eq!(users.list().len(), 1);
+    assert!(neemo.collection("orders").unwrap().list().is_empty());
+}
@@ -0,0 +1,130 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use neemo::{Document, KvIter, KvResult, KvStore, MemoryStore, Neemo, NeemoError};
+use serde_json::Value;
+use tempfile::TempDir;
+
+/// Builds a document from a JSON object.
+pub fn doc(value: Value) -> Document {
+    Document { data: serde_json::from_value(value).expect("document must be a JSON object") }
+}
+
+/// Opens a new database in a temporary directory. The directory is removed
+/// when the returned guard is dropped, so keep it alive alongside the handle.
+pub fn open_temp() -> (TempDir, Neemo) {
+    let dir = tempfile::tempdir().unwrap();
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    (dir, neemo)
+}
+
+/// Returns the database path inside a temporary directory.
+pub fn db_path(dir: &TempDir) -> String {
+    dir.path().join("db").to_str().unwrap().to_string()
+}
+
+/// Returns the path of a file inside a temporary directory.
+pub fn file_path(dir: &TempDir, name: &str) -> String {
+    dir.path().join(name).to_str().unwrap().to_string()
+}
+
+/// Returns the value of `field` in each document, in order.
+pub fn values(docs: &[Document], field: &str) -> Vec<Value> {
+    docs.iter().map(|doc| doc.get_path(field).cloned().unwrap_or(Value::Null)).collect()
+}
+
+/// Counters and fault switches shared between a test and its `ProbeStore`.
+#[derive(Default)]
+pub struct Probe {
+    /// Number of `get` calls made on the store.
+    pub gets: AtomicUsize,
+    /// Number of `iter`, `scan_prefix` and `range` calls made on the store.
+    pub scans: AtomicUsize,
+    /// When set, the next `insert` panics instead of writing.
+    pub panic_on_insert: AtomicBool,
+    /// When set, `range` yields an error instead of entries.
+    pub fail_range: AtomicBool,
+    /// Runs at the start of every `range` call, before the store is read.
+    pub before_range: Mutex<Option<Box<dyn FnMut() + Send>>>,
+    /// Runs once, after the next `get` has read the store.
+    pub after_get: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    /// Runs once, after the next `scan_prefix` has read the store.
+    pub after_scan_prefix: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+}
+
+/// An in-memory store that records reads and can be made to panic, for tests
+/// that need to observe or disturb what `Neemo` does with its storage.
+pub struct ProbeStore {
+    inner: MemoryStore,
+    probe: Arc<Probe>,
+}
+
+impl ProbeStore {
+    /// Creates an empty store and the probe that watches it.
+    pub fn new() -> (Self, Arc<Probe>) {
+        let probe = Arc::new(Probe::default());
+        (ProbeStore { inner: MemoryStore::new(), probe: probe.clone() }, probe)
+    }
+}
+
+impl KvStore for ProbeStore {
+    fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        self.probe.gets.fetch_add(1, Ordering::SeqCst);
+        let value = self.inner.get(key);
+        let hook = self.probe.after_get.lock().unwrap().take();
+        if let Some(hook) = hook {
+            hook();
+        }
+        value
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        if self.probe.panic_on_insert.swap(false, Ordering::SeqCst) {
+            panic!("injected insert failure");
+        }
+        self.inner.insert(key, value)
+    }
+
+    fn remove(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        self.inner.remove(key)
+    }
+
+    fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> KvResult<bool> {
+        self.inner.compare_and_swap(key, old, new)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> KvIter<'static> {
+        self.probe.scans.fetch_add(1, Ordering::SeqCst);
+        let entries = self.inner.scan_prefix(prefix);
+        let hook = self.probe.after_scan_prefix.lock().unwrap().take();
+        if let Some(hook) = hook {
+            hook();
+        }
+        entries
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> KvIter<'static> {
+        self.probe.scans.fetch_add(1, Ordering::SeqCst);
+        if let Some(hook) = self.probe.before_range.lock().unwrap().as_mut() {
+            hook();
+        }
+        if self.probe.fail_range.load(Ordering::SeqCst) {
+            return Box::new(std::iter::once(Err(NeemoError::Storage("injected range failure".to_string()))));
+        }
+        self.inner.range(start, end)
+    }
+
+    fn iter(&self) -> KvIter<'static> {
+        self.probe.scans.fetch_add(1, Ordering::SeqCst);
+        self.inner.iter()
+    }
+
+    fn flush(&self) -> KvResult<()> {
+        self.inner.flush()
+    }
+}
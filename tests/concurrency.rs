@@ -0,0 +1,200 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common::{doc, file_path, open_temp, ProbeStore};
+use neemo::{Neemo, NeemoError, TextMatch};
+use serde_json::json;
+
+#[test]
+fn writes_succeed_after_a_writer_panics() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0);
+    neemo.create_index("n").unwrap();
+
+    probe.panic_on_insert.store(true, Ordering::SeqCst);
+    let crashed = thread::scope(|scope| scope.spawn(|| neemo.insert("a", doc(json!({ "n": 1 })))).join());
+    assert!(crashed.is_err());
+
+    neemo.insert("b", doc(json!({ "n": 2 }))).unwrap();
+    neemo.update("b", doc(json!({ "n": 3 }))).unwrap();
+    assert_eq!(neemo.get("b").unwrap().data["n"], json!(3));
+    assert_eq!(neemo.query("n", json!(3)).unwrap().len(), 1);
+}
+
+#[test]
+fn reads_and_writes_proceed_between_export_chunks() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0);
+    let docs: Vec<_> = (0..2_500).map(|i| (format!("doc:{i:04}"), doc(json!({ "n": i })))).collect();
+    neemo.insert_many(&docs).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "export.jsonl");
+
+    let (request, requests) = mpsc::channel::<usize>();
+    let (reply, replies) = mpsc::channel::<bool>();
+    let served = thread::scope(|scope| {
+        let neemo = &neemo;
+        scope.spawn(move || {
+            for chunk in requests {
+                let read = neemo.get("doc:0000").is_some();
+                let written = neemo.insert(&format!("extra:{chunk}"), doc(json!({ "n": chunk }))).is_ok();
+                reply.send(read && written).unwrap();
+            }
+        });
+
+        // Before each chunk is read, the reader must get and insert a
+        // document; a lock held across chunks would make it time out.
+        let served = Arc::new(AtomicUsize::new(0));
+        let (counter, mut chunk) = (served.clone(), 0);
+        *probe.before_range.lock().unwrap() = Some(Box::new(move || {
+            chunk += 1;
+            request.send(chunk).unwrap();
+            if replies.recv_timeout(Duration::from_secs(5)) == Ok(true) {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+        neemo.export(&path).unwrap();
+        // Dropping the hook closes the channel and ends the reader.
+        probe.before_range.lock().unwrap().take();
+        served.load(Ordering::SeqCst)
+    });
+
+    assert!(served >= 3, "reader served {served} times");
+    let exported = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(exported.lines().filter(|line| line.contains("\"doc:")).count(), 2_500);
+}
+
+#[test]
+fn threads_read_and_write_at_the_same_time() {
+    let (_dir, neemo) = open_temp();
+    let neemo = neemo.with_document_cache(64);
+    neemo.create_index("writer").unwrap();
+    let writing = AtomicUsize::new(4);
+
+    thread::scope(|scope| {
+        for writer in 0..4 {
+            let (neemo, writing) = (&neemo, &writing);
+            scope.spawn(move || {
+                for i in 0..200 {
+                    neemo.insert(&format!("w{writer}:{i:03}"), doc(json!({ "writer": writer, "i": i }))).unwrap();
+                }
+                writing.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        for reader in 0..4 {
+            let (neemo, writing) = (&neemo, &writing);
+            scope.spawn(move || {
+                while writing.load(Ordering::SeqCst) > 0 {
+                    // A document is only visible once it is fully written.
+                    if let Some(found) = neemo.get(&format!("w{reader}:000")) {
+                        assert_eq!(found.data["writer"], json!(reader));
+                    }
+                    for found in neemo.query("writer", json!(reader)).unwrap() {
+                        assert_eq!(found.data["writer"], json!(reader));
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(neemo.count().unwrap(), 800);
+    for writer in 0..4 {
+        assert_eq!(neemo.count_query("writer", json!(writer)).unwrap(), 200);
+    }
+}
+
+#[test]
+fn concurrent_increments_are_never_lost() {
+    let (_dir, neemo) = open_temp();
+    neemo.insert("page", doc(json!({ "title": "home" }))).unwrap();
+    let threads = 8;
+    let per_thread = 25;
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..per_thread {
+                    neemo.increment("page", "views", 1.0).unwrap();
+                }
+            });
+        }
+    });
+
+    let total = threads * per_thread;
+    assert_eq!(neemo.get("page").unwrap().data["views"], json!(total));
+    assert_eq!(neemo.get("page").unwrap().data["title"], json!("home"));
+    assert_eq!(neemo.increment("missing", "likes", 2.5).unwrap(), 2.5);
+    assert!(matches!(neemo.increment("page", "title", 1.0), Err(NeemoError::NotNumeric(field)) if field == "title"));
+}
+
+/// A one-shot hook of a `Probe`.
+type Hook = Mutex<Option<Box<dyn FnOnce() + Send>>>;
+
+/// Runs `first` and, once it reaches `hook`, `second` on another thread.
+/// Returns whether `second` finished before `first` went on.
+fn overlaps(hook: &Hook, first: impl FnOnce(), second: impl FnOnce() + Send) -> bool {
+    let (go, started) = mpsc::channel();
+    let (finished, done) = mpsc::channel();
+    let overlapped = Arc::new(AtomicBool::new(false));
+    let flag = overlapped.clone();
+    *hook.lock().unwrap() = Some(Box::new(move || {
+        go.send(()).unwrap();
+        flag.store(done.recv_timeout(Duration::from_secs(5)).is_ok(), Ordering::SeqCst);
+    }));
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            started.recv().unwrap();
+            second();
+            finished.send(()).unwrap();
+        });
+        first();
+    });
+    overlapped.load(Ordering::SeqCst)
+}
+
+#[test]
+fn full_text_searches_do_not_wait_for_each_other() {
+    let (text, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(ProbeStore::new().0, ProbeStore::new().0).with_text_index(text);
+    neemo.insert("a", doc(json!({ "bio": "likes fjords" }))).unwrap();
+    let search = || neemo.full_text_search_with("fjords", None, TextMatch::WholeWord).unwrap().documents.len();
+
+    assert!(overlaps(&probe.after_get, || assert_eq!(search(), 1), || assert_eq!(search(), 1)));
+}
+
+#[test]
+fn expiry_checks_do_not_wait_for_each_other() {
+    let (expiry, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(ProbeStore::new().0, ProbeStore::new().0).with_expiry_store(expiry);
+    neemo.insert_with_ttl("a", doc(json!({ "n": 1 })), Duration::from_secs(3600)).unwrap();
+    neemo.insert("b", doc(json!({ "n": 2 }))).unwrap();
+
+    assert!(overlaps(&probe.after_get, || assert!(neemo.get("a").is_some()), || assert_eq!(neemo.list().len(), 2)));
+}
+
+#[test]
+fn blob_reads_do_not_wait_for_each_other() {
+    let (blobs, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(ProbeStore::new().0, ProbeStore::new().0).with_blob_store(blobs);
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    neemo.insert_blob("a", "photo", b"jpeg").unwrap();
+    let read = || assert_eq!(neemo.get_blob("a", "photo").unwrap().as_deref(), Some(&b"jpeg"[..]));
+
+    assert!(overlaps(&probe.after_get, read, read));
+}
+
+#[test]
+fn history_reads_do_not_wait_for_each_other() {
+    let (history, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(ProbeStore::new().0, ProbeStore::new().0).with_history_store(history);
+    neemo.set_history_limit(Some(3));
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    neemo.insert("a", doc(json!({ "n": 2 }))).unwrap();
+    let read = || assert_eq!(neemo.history("a"), [doc(json!({ "n": 1 }))]);
+
+    assert!(overlaps(&probe.after_scan_prefix, read, read));
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{db_path, doc, file_path, open_temp};
+use neemo::{Neemo, NeemoConfig};
+use serde_json::json;
+
+#[test]
+fn backup_restores_documents_and_indexes_into_a_fresh_database() {
+    let (dir, neemo) = open_temp();
+    neemo.create_index("role").unwrap();
+    neemo.insert("ann", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.insert("bo", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.insert("cy", doc(json!({ "role": "user" }))).unwrap();
+    let backup = file_path(&dir, "backup");
+    neemo.backup(&backup).unwrap();
+    assert!(neemo.backup(&backup).is_err());
+    drop(neemo);
+
+    let restored_path = file_path(&dir, "restored");
+    let restored = Neemo::open(&restored_path).unwrap();
+    restored.restore(&backup).unwrap();
+    drop(restored);
+
+    let reopened = Neemo::open(&restored_path).unwrap();
+    assert_eq!(reopened.list_indexes(), vec!["role".to_string()]);
+    assert_eq!(reopened.list_with_keys().len(), 3);
+    let admins = reopened.query_with_keys("role", json!("admin")).unwrap();
+    assert_eq!(admins.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["ann", "bo"]);
+}
+
+/// Copies a directory tree, as a crash would leave it on disk.
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+#[test]
+fn flushed_writes_survive_without_a_clean_close() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    let neemo = Neemo::open_with_config(&path, NeemoConfig::new().flush_every_ms(None)).unwrap();
+    neemo.create_index("role").unwrap();
+    neemo.insert("ann", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.insert("bo", doc(json!({ "role": "user" }))).unwrap();
+    neemo.flush().unwrap();
+
+    // The copy is taken while the database is still open, so only what
+    // `flush` wrote is in it.
+    let copy = file_path(&dir, "copy");
+    copy_dir(Path::new(&path), Path::new(&copy));
+    let reopened = Neemo::open(&copy).unwrap();
+    assert_eq!(reopened.get("ann").unwrap().data["role"], json!("admin"));
+    assert_eq!(reopened.query("role", json!("user")).unwrap().len(), 1);
+    drop(neemo);
+}
+
+#[test]
+fn databases_open_with_tuned_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    let config = NeemoConfig::new()
+        .cache_capacity(4 * 1024 * 1024)
+        .flush_every_ms(Some(50))
+        .mode(sled::Mode::HighThroughput);
+    {
+        let neemo = Neemo::open_with_config(&path, config.clone()).unwrap();
+        neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    }
+    let neemo = Neemo::open_with_config(&path, config).unwrap();
+    assert_eq!(neemo.get("a").unwrap().data["n"], json!(1));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_databases_reopen_with_compression() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    let config = NeemoConfig::new().compression(true);
+    {
+        let neemo = Neemo::open_with_config(&path, config.clone()).unwrap();
+        neemo.insert("a", doc(json!({ "text": "compressible ".repeat(100) }))).unwrap();
+    }
+    let neemo = Neemo::open_with_config(&path, config).unwrap();
+    assert_eq!(neemo.get("a").unwrap().data["text"], json!("compressible ".repeat(100)));
+}
+
+#[test]
+fn dropping_the_handle_keeps_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    let neemo = Neemo::open_with_config(&path, NeemoConfig::new().flush_every_ms(None)).unwrap();
+    neemo.create_index("role").unwrap();
+    neemo.insert("ann", doc(json!({ "role": "admin" }))).unwrap();
+    drop(neemo);
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert_eq!(neemo.get("ann").unwrap().data["role"], json!("admin"));
+    assert_eq!(neemo.query("role", json!("admin")).unwrap().len(), 1);
+    neemo.insert("bo", doc(json!({ "role": "user" }))).unwrap();
+    neemo.close().unwrap();
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert!(neemo.get("bo").is_some());
+}
+
+/// Inserts and deletes documents over and over, leaving a few behind.
+fn churn(neemo: &Neemo) {
+    for round in 0..5 {
+        for i in 0..200 {
+            neemo.insert(&format!("doc{}", i), doc(json!({ "round": round, "body": "x".repeat(1024) }))).unwrap();
+        }
+        for i in 10..200 {
+            neemo.delete(&format!("doc{}", i)).unwrap();
+        }
+    }
+    neemo.flush().unwrap();
+}
+
+#[test]
+fn checkpoint_reclaims_space_freed_by_deletes() {
+    let dir = tempfile::tempdir().unwrap();
+    let baseline = Neemo::open(&file_path(&dir, "baseline")).unwrap();
+    churn(&baseline);
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    churn(&neemo);
+
+    let before = neemo.size_on_disk().unwrap();
+    neemo.checkpoint().unwrap();
+    let after = neemo.size_on_disk().unwrap();
+    assert!(after < before, "{} bytes before, {} after", before, after);
+    assert!(after < baseline.size_on_disk().unwrap());
+
+    // Churning again starts from the smaller files.
+    churn(&neemo);
+    churn(&baseline);
+    neemo.checkpoint().unwrap();
+    assert!(neemo.size_on_disk().unwrap() < baseline.size_on_disk().unwrap());
+    assert_eq!(neemo.count().unwrap(), 10);
+}
+
+#[test]
+fn checkpoint_keeps_documents_indexes_and_collections() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    let neemo = Neemo::open(&path).unwrap();
+    neemo.create_index("role").unwrap();
+    neemo.insert("ann", doc(json!({ "role": "admin", "bio": "likes rust" }))).unwrap();
+    neemo.insert("bo", doc(json!({ "role": "user" }))).unwrap();
+    neemo.insert_blob("ann", "avatar", &[1, 2, 3]).unwrap();
+    let users = neemo.collection("users").unwrap();
+    users.insert("cy", doc(json!({ "role": "admin" }))).unwrap();
+    churn(&neemo);
+
+    let before = neemo.size_on_disk().unwrap();
+    neemo.checkpoint().unwrap();
+    assert!(neemo.size_on_disk().unwrap() < before);
+    assert_eq!(neemo.query("role", json!("admin")).unwrap().len(), 1);
+    assert_eq!(neemo.full_text_search("rust", None).unwrap().documents.len(), 1);
+    assert_eq!(neemo.get_blob("ann", "avatar").unwrap(), Some(vec![1, 2, 3]));
+    // Handles taken before the checkpoint read and write the new files.
+    assert!(users.get("cy").is_some());
+    users.insert("dee", doc(json!({ "role": "user" }))).unwrap();
+    neemo.insert("eve", doc(json!({ "role": "admin" }))).unwrap();
+    drop(users);
+    drop(neemo);
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert_eq!(neemo.list_indexes(), vec!["role".to_string()]);
+    assert_eq!(neemo.query("role", json!("admin")).unwrap().len(), 2);
+    assert_eq!(neemo.collection("users").unwrap().count().unwrap(), 2);
+    assert!(!Path::new(&path).join("data.old").exists());
+}
+
+#[test]
+fn checkpoint_leaves_files_alone_when_a_copy_would_not_be_smaller() {
+    let (_dir, neemo) = open_temp();
+    neemo.insert("ann", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.flush().unwrap();
+    let before = neemo.size_on_disk().unwrap();
+    neemo.checkpoint().unwrap();
+    assert_eq!(neemo.size_on_disk().unwrap(), before);
+    assert!(neemo.get("ann").is_some());
+}
+
+#[test]
+fn an_interrupted_checkpoint_is_finished_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    {
+        let neemo = Neemo::open(&path).unwrap();
+        neemo.insert("ann", doc(json!({ "role": "admin" }))).unwrap();
+    }
+    // A crash between moving the old store aside and moving the copy in.
+    let root = Path::new(&path);
+    copy_dir(&root.join("data"), &root.join("data.checkpoint"));
+    fs::rename(root.join("data"), root.join("data.old")).unwrap();
+    copy_dir(&root.join("index"), &root.join("index.checkpoint"));
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert_eq!(neemo.get("ann").unwrap().data["role"], json!("admin"));
+    for leftover in ["data.checkpoint", "data.old", "index.checkpoint"] {
+        assert!(!root.join(leftover).exists(), "{} was left behind", leftover);
+    }
+}
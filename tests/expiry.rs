@@ -0,0 +1,79 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{doc, open_temp};
+use neemo::{MemoryStore, Neemo, NeemoError};
+use serde_json::json;
+
+#[test]
+fn expired_documents_are_hidden_and_purged() {
+    let (_dir, neemo) = open_temp();
+    neemo.create_index("kind").unwrap();
+    neemo.insert_with_ttl("session:a", doc(json!({ "kind": "session" })), Duration::from_millis(1)).unwrap();
+    neemo.insert_with_ttl("session:b", doc(json!({ "kind": "session" })), Duration::from_millis(1)).unwrap();
+    neemo.insert_with_ttl("session:c", doc(json!({ "kind": "session" })), Duration::from_secs(3600)).unwrap();
+    neemo.insert("user:a", doc(json!({ "kind": "user" }))).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(neemo.list().len(), 2);
+    assert_eq!(neemo.count().unwrap(), 2);
+    assert_eq!(neemo.purge_expired().unwrap(), 2);
+    assert_eq!(neemo.purge_expired().unwrap(), 0);
+    assert_eq!(neemo.raw_iter().count(), 2);
+    assert_eq!(neemo.query("kind", json!("session")).unwrap().len(), 1);
+}
+
+#[test]
+fn get_deletes_an_expired_document() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("kind").unwrap();
+    neemo.insert_with_ttl("a", doc(json!({ "kind": "session" })), Duration::from_millis(1)).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    assert!(neemo.get("a").is_none());
+    assert_eq!(neemo.raw_iter().count(), 0);
+    assert!(!neemo.exists_value("kind", json!("session")));
+}
+
+#[test]
+fn inserting_again_clears_the_expiry_time() {
+    let neemo = Neemo::in_memory();
+    neemo.insert_with_ttl("a", doc(json!({ "n": 1 })), Duration::from_millis(1)).unwrap();
+    neemo.insert("a", doc(json!({ "n": 2 }))).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(neemo.get("a").unwrap().data["n"], json!(2));
+    assert_eq!(neemo.purge_expired().unwrap(), 0);
+}
+
+#[test]
+fn ttl_needs_an_expiry_store() {
+    let neemo = Neemo::with_stores(MemoryStore::new(), MemoryStore::new());
+    let result = neemo.insert_with_ttl("a", doc(json!({ "n": 1 })), Duration::from_secs(1));
+    assert!(matches!(result, Err(NeemoError::ExpiryUnsupported)));
+    assert!(neemo.get("a").is_none());
+}
+
+#[test]
+fn summaries_leave_out_expired_documents() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("plan").unwrap();
+    neemo.insert("user:a", doc(json!({ "plan": "pro", "team": "red", "seats": 3 }))).unwrap();
+    neemo.insert("user:b", doc(json!({ "plan": "free", "team": "red", "seats": 1 }))).unwrap();
+    neemo.insert_with_ttl("user:c", doc(json!({ "plan": "trial", "team": "blue", "seats": 10 })), Duration::from_millis(1)).unwrap();
+    neemo.insert_with_ttl("guest:d", doc(json!({ "plan": "pro", "team": "blue", "seats": 10 })), Duration::from_millis(1)).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    assert!(!neemo.exists_value("plan", json!("trial")));
+    assert!(!neemo.exists_value("team", json!("blue")));
+    assert_eq!(neemo.facet("plan"), vec![(json!("free"), 1), (json!("pro"), 1)]);
+    assert_eq!(neemo.facet("team"), vec![(json!("red"), 2)]);
+    assert_eq!(neemo.fold(0, |total, doc| total + doc.data["seats"].as_i64().unwrap()), 4);
+    assert_eq!(neemo.aggregate("seats", "sum").unwrap(), Some(json!(4.0)));
+    assert_eq!(neemo.aggregate_grouped("team", "seats", "count").len(), 1);
+    assert_eq!(neemo.count_by_prefix("user:"), 2);
+    assert_eq!(neemo.count_by_prefix("guest:"), 0);
+    assert_eq!(neemo.child_prefixes("", ':'), vec![("user".to_string(), 2)]);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::{db_path, doc, values};
+use neemo::Neemo;
+use serde_json::json;
+
+#[test]
+fn three_updates_leave_three_versions_newest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let neemo = Neemo::open(&db_path(&dir)).unwrap();
+        neemo.set_history_limit(Some(10));
+        neemo.insert("page", doc(json!({ "rev": 1 }))).unwrap();
+        neemo.update("page", doc(json!({ "rev": 2 }))).unwrap();
+        neemo.insert("page", doc(json!({ "rev": 3 }))).unwrap();
+        neemo.patch("page", doc(json!({ "rev": 4 }))).unwrap();
+        neemo.insert("other", doc(json!({ "rev": 1 }))).unwrap();
+    }
+
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    assert_eq!(values(&neemo.history("page"), "rev"), vec![json!(3), json!(2), json!(1)]);
+    assert!(neemo.history("other").is_empty());
+    neemo.delete("page").unwrap();
+    assert_eq!(neemo.history("page").len(), 3);
+}
+
+#[test]
+fn the_limit_drops_the_oldest_versions() {
+    let neemo = Neemo::in_memory();
+    neemo.set_history_limit(Some(2));
+    for rev in 1..=5 {
+        neemo.insert("page", doc(json!({ "rev": rev }))).unwrap();
+    }
+    assert_eq!(values(&neemo.history("page"), "rev"), vec![json!(4), json!(3)]);
+
+    neemo.set_history_limit(None);
+    neemo.insert("page", doc(json!({ "rev": 6 }))).unwrap();
+    assert_eq!(values(&neemo.history("page"), "rev"), vec![json!(4), json!(3)]);
+}
+
+#[test]
+fn a_limit_of_zero_keeps_no_versions() {
+    let neemo = Neemo::in_memory();
+    neemo.set_history_limit(Some(0));
+    neemo.insert("page", doc(json!({ "rev": 1 }))).unwrap();
+    neemo.insert("page", doc(json!({ "rev": 2 }))).unwrap();
+    assert!(neemo.history("page").is_empty());
+
+    neemo.set_history_limit(Some(5));
+    neemo.insert("page", doc(json!({ "rev": 3 }))).unwrap();
+    assert_eq!(neemo.history("page").len(), 1);
+    neemo.set_history_limit(Some(0));
+    neemo.insert("page", doc(json!({ "rev": 4 }))).unwrap();
+    assert!(neemo.history("page").is_empty());
+}
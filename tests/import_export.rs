@@ -0,0 +1,224 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+
+use common::{doc, file_path, open_temp, ProbeStore};
+use neemo::{ExportRecord, FieldType, ImportPolicy, Neemo, NeemoError, Schema};
+use serde_json::json;
+
+#[test]
+fn import_restores_schema_indexes_and_id_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "users.jsonl");
+
+    let source = Neemo::in_memory();
+    source.set_id_field("email");
+    source.set_schema(Schema::new("").require("age", FieldType::Number));
+    source.create_index("age").unwrap();
+    source.insert_doc(doc(json!({ "email": "ann@example.com", "age": 31 }))).unwrap();
+    source.export(&path).unwrap();
+
+    let target = Neemo::in_memory();
+    target.import(&path).unwrap();
+    assert_eq!(target.list_indexes(), vec!["age".to_string()]);
+    assert_eq!(target.get("ann@example.com").unwrap().data["age"], json!(31));
+
+    let duplicate = target.insert_doc(doc(json!({ "email": "ann@example.com", "age": 40 })));
+    assert!(matches!(duplicate, Err(NeemoError::DuplicateKey(_))));
+    let invalid = target.insert_doc(doc(json!({ "email": "bo@example.com", "age": "old" })));
+    assert!(matches!(invalid, Err(NeemoError::Validation(_))));
+    assert_eq!(target.insert_doc(doc(json!({ "email": "bo@example.com", "age": 25 }))).unwrap(), "bo@example.com");
+}
+
+/// Writes one export line per document, with `bad_line` (1-based) breaking
+/// the `n` schema and line 150 not a document at all.
+fn write_import_file(path: &str, lines: usize, bad_line: Option<usize>) {
+    let content: Vec<String> = (1..=lines)
+        .map(|line| match line {
+            150 => "not json".to_string(),
+            _ => {
+                let n = if Some(line) == bad_line { json!("oops") } else { json!(line) };
+                let record = ExportRecord { key: format!("k{:04}", line), doc: doc(json!({ "n": n })) };
+                serde_json::to_string(&record).unwrap()
+            }
+        })
+        .collect();
+    std::fs::write(path, content.join("\n")).unwrap();
+}
+
+#[test]
+fn batched_import_reports_where_it_stopped_and_resumes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "big.jsonl");
+    write_import_file(&path, 1000, Some(437));
+
+    let neemo = Neemo::in_memory();
+    neemo.set_schema(Schema::new("k").require("n", FieldType::Number));
+    let report = neemo.import_batched(&path, 100, 0);
+    assert!(matches!(report.error, Some(NeemoError::Validation(_))));
+    assert_eq!(report.last_committed_line, 400);
+    assert_eq!(report.imported, 399);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(neemo.count().unwrap(), 399);
+    assert!(neemo.get("k0400").is_some());
+    assert!(neemo.get("k0401").is_none());
+
+    write_import_file(&path, 1000, None);
+    let resumed = neemo.import_batched(&path, 100, report.last_committed_line);
+    assert!(resumed.error.is_none());
+    assert_eq!(resumed.imported, 600);
+    assert_eq!(resumed.last_committed_line, 1000);
+    assert_eq!(neemo.count().unwrap(), 999);
+}
+
+#[test]
+fn compact_export_collapses_identical_documents_only_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let neemo = Neemo::in_memory();
+    neemo.insert("a", doc(json!({ "name": "Ann", "tags": { "z": 1, "a": 2 } }))).unwrap();
+    neemo.insert("b", doc(json!({ "tags": { "a": 2, "z": 1 }, "name": "Ann" }))).unwrap();
+    neemo.insert("c", doc(json!({ "name": "Bo" }))).unwrap();
+
+    let kept = file_path(&dir, "kept.jsonl");
+    assert_eq!(neemo.compact_export(&kept, false).unwrap(), 0);
+    let lines: Vec<String> = std::fs::read_to_string(&kept).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], r#"{"doc":{"data":{"name":"Ann","tags":{"a":2,"z":1}}},"key":"a"}"#);
+
+    let deduped = file_path(&dir, "deduped.jsonl");
+    assert_eq!(neemo.compact_export(&deduped, true).unwrap(), 1);
+    let target = Neemo::in_memory();
+    target.import(&deduped).unwrap();
+    assert!(target.get("a").is_some());
+    assert!(target.get("b").is_none());
+    assert!(target.get("c").is_some());
+}
+
+#[test]
+fn export_and_import_round_trip_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "export.jsonl");
+    let (_db_dir, neemo) = open_temp();
+    neemo.create_index("city").unwrap();
+    neemo.insert("user:ann", doc(json!({ "name": "Ann", "city": "NYC", "tags": ["a", "b"] }))).unwrap();
+    neemo.insert("user:bo", doc(json!({ "name": "Bo", "address": { "city": "LA" } }))).unwrap();
+    let original = neemo.list_with_keys();
+
+    neemo.export(&path).unwrap();
+    let line = std::fs::read_to_string(&path).unwrap().lines().next().unwrap().to_string();
+    assert_eq!(serde_json::from_str::<ExportRecord>(&line).unwrap().key, "user:ann");
+
+    neemo.clear().unwrap();
+    assert!(neemo.list_with_keys().is_empty());
+    neemo.import(&path).unwrap();
+    assert_eq!(neemo.list_with_keys(), original);
+    assert_eq!(neemo.query("city", json!("NYC")).unwrap().len(), 1);
+}
+
+#[test]
+fn csv_round_trips_documents_with_different_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "people.csv");
+    let source = Neemo::in_memory();
+    source.insert("a", doc(json!({ "name": "Ann", "age": 31 }))).unwrap();
+    source.insert("b", doc(json!({ "name": "Bo, \"Jr\"\nthe second", "address": { "city": "LA" } }))).unwrap();
+    source.insert("c", doc(json!({ "tags": ["x", "y"], "active": true }))).unwrap();
+    source.export_csv(&path).unwrap();
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(csv.lines().next().unwrap(), "_key,active,address,age,name,tags");
+    assert!(csv.contains("\na,,,31,Ann,\n"));
+
+    let target = Neemo::in_memory();
+    assert_eq!(target.import_csv(&path).unwrap(), 3);
+    assert_eq!(target.list_with_keys(), source.list_with_keys());
+    assert!(!target.get("a").unwrap().data.contains_key("tags"));
+}
+
+#[test]
+fn csv_cells_that_are_not_json_import_as_strings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "plain.csv");
+    std::fs::write(&path, "name,score,note\nAnn,12,\"hello, world\"\nBo,,plain text\n").unwrap();
+
+    let neemo = Neemo::in_memory();
+    assert_eq!(neemo.import_csv(&path).unwrap(), 2);
+    let ann = neemo.query("name", json!("Ann")).unwrap();
+    assert_eq!(ann[0].data["score"], json!(12));
+    assert_eq!(ann[0].data["note"], json!("hello, world"));
+    let bo = neemo.query("name", json!("Bo")).unwrap();
+    assert!(!bo[0].data.contains_key("score"));
+    assert_eq!(bo[0].data["note"], json!("plain text"));
+}
+
+#[test]
+fn exports_report_storage_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0);
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    probe.fail_range.store(true, Ordering::SeqCst);
+
+    let failed = |result: Result<(), NeemoError>| matches!(result, Err(NeemoError::Storage(message)) if message.contains("injected"));
+    assert!(failed(neemo.export(&file_path(&dir, "out.jsonl"))));
+    assert!(failed(neemo.compact_export(&file_path(&dir, "compact.jsonl"), false).map(drop)));
+    assert!(failed(neemo.export_csv(&file_path(&dir, "out.csv"))));
+
+    probe.fail_range.store(false, Ordering::SeqCst);
+    neemo.export(&file_path(&dir, "out.jsonl")).unwrap();
+}
+
+#[cfg(feature = "flate2")]
+#[test]
+fn compressed_archives_round_trip_and_are_smaller() {
+    let dir = tempfile::tempdir().unwrap();
+    let (plain, archive) = (file_path(&dir, "plain.jsonl"), file_path(&dir, "archive.jsonl.gz"));
+    let source = Neemo::in_memory();
+    for i in 0..500 {
+        source.insert(&format!("doc:{i:03}"), doc(json!({ "n": i, "text": "the same words repeated in every document" }))).unwrap();
+    }
+    source.export(&plain).unwrap();
+    source.export_archive(&archive).unwrap();
+    let size = |path: &str| std::fs::metadata(path).unwrap().len();
+    assert!(size(&archive) * 4 < size(&plain), "{} bytes compressed, {} plain", size(&archive), size(&plain));
+
+    let from_archive = Neemo::in_memory();
+    from_archive.import_archive(&archive).unwrap();
+    assert_eq!(from_archive.count().unwrap(), 500);
+    assert_eq!(from_archive.list_with_keys(), source.list_with_keys());
+
+    // A plain export is recognised and imported as is.
+    let from_plain = Neemo::in_memory();
+    from_plain.import_archive(&plain).unwrap();
+    assert_eq!(from_plain.count().unwrap(), 500);
+}
+
+#[test]
+fn import_policies_settle_conflicting_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "incoming.jsonl");
+    let incoming = Neemo::in_memory();
+    incoming.insert("a", doc(json!({ "v": "imported" }))).unwrap();
+    incoming.insert("b", doc(json!({ "v": "imported" }))).unwrap();
+    incoming.export(&path).unwrap();
+    let existing = || {
+        let neemo = Neemo::in_memory();
+        neemo.insert("a", doc(json!({ "v": "existing" }))).unwrap();
+        neemo
+    };
+
+    let overwritten = existing();
+    overwritten.import_with_policy(&path, ImportPolicy::Overwrite).unwrap();
+    assert_eq!(overwritten.get("a").unwrap().data["v"], json!("imported"));
+    assert_eq!(overwritten.get("b").unwrap().data["v"], json!("imported"));
+
+    let skipped = existing();
+    skipped.import_with_policy(&path, ImportPolicy::Skip).unwrap();
+    assert_eq!(skipped.get("a").unwrap().data["v"], json!("existing"));
+    assert_eq!(skipped.get("b").unwrap().data["v"], json!("imported"));
+
+    let failed = existing();
+    let result = failed.import_with_policy(&path, ImportPolicy::Fail);
+    assert!(matches!(result, Err(NeemoError::DuplicateKey(key)) if key == "a"));
+    assert_eq!(failed.get("a").unwrap().data["v"], json!("existing"));
+}
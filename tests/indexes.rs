@@ -0,0 +1,258 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+
+use common::{db_path, doc, open_temp, values, ProbeStore};
+use neemo::{Neemo, NeemoError};
+use serde_json::json;
+
+#[test]
+fn long_values_are_capped_in_index_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let index = db.open_tree("index").unwrap();
+    let neemo = Neemo::with_stores(db.open_tree("docs").unwrap(), index.clone());
+    let cap = 64;
+    neemo.set_index_value_cap(Some(cap));
+    neemo.create_index("bio").unwrap();
+
+    let long = "x".repeat(10 * 1024);
+    let similar = format!("{}y", &long[..long.len() - 1]);
+    neemo.insert("long", doc(json!({ "bio": long }))).unwrap();
+    neemo.insert("similar", doc(json!({ "bio": similar }))).unwrap();
+
+    let longest = index.iter().keys().map(|key| key.unwrap().len()).max().unwrap();
+    assert!(longest <= "bio:".len() + cap + "#".len() + 16, "index key of {} bytes", longest);
+
+    let found = neemo.query("bio", json!(long)).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].data["bio"], json!(long));
+}
+
+#[test]
+fn exists_value_probes_only_the_index() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0);
+    neemo.create_index("email").unwrap();
+    neemo.insert("u1", doc(json!({ "email": "ann@example.com" }))).unwrap();
+
+    let reads = probe.gets.load(Ordering::SeqCst);
+    assert!(neemo.exists_value("email", json!("ann@example.com")));
+    assert!(!neemo.exists_value("email", json!("bo@example.com")));
+    assert_eq!(probe.gets.load(Ordering::SeqCst), reads);
+
+    neemo.delete("u1").unwrap();
+    assert!(!neemo.exists_value("email", json!("ann@example.com")));
+}
+
+#[test]
+fn documents_sharing_a_value_are_all_returned() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("status").unwrap();
+    for key in ["a", "b", "c"] {
+        neemo.insert(key, doc(json!({ "status": "active" }))).unwrap();
+    }
+    neemo.insert("d", doc(json!({ "status": "inactive" }))).unwrap();
+
+    let active = neemo.query_with_keys("status", json!("active")).unwrap();
+    assert_eq!(active.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+}
+
+#[test]
+fn deleting_one_document_keeps_others_with_the_same_value() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("role").unwrap();
+    neemo.insert("ann", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.insert("bo", doc(json!({ "role": "admin" }))).unwrap();
+
+    neemo.delete("ann").unwrap();
+    let admins = neemo.query_with_keys("role", json!("admin")).unwrap();
+    assert_eq!(admins.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["bo"]);
+
+    neemo.delete("bo").unwrap();
+    assert!(neemo.query("role", json!("admin")).unwrap().is_empty());
+    assert!(!neemo.exists_value("role", json!("admin")));
+}
+
+#[test]
+fn update_replaces_the_index_entries_of_changed_fields() {
+    let neemo = Neemo::in_memory();
+    for field in ["city", "zip", "tag"] {
+        neemo.create_index(field).unwrap();
+    }
+    neemo.insert("a", doc(json!({ "city": "NYC", "zip": "10001" }))).unwrap();
+
+    neemo.update("a", doc(json!({ "city": "LA", "tag": "moved" }))).unwrap();
+    assert!(neemo.query("city", json!("NYC")).unwrap().is_empty());
+    assert_eq!(neemo.query("city", json!("LA")).unwrap().len(), 1);
+    assert!(neemo.query("zip", json!("10001")).unwrap().is_empty());
+    assert_eq!(neemo.query("tag", json!("moved")).unwrap().len(), 1);
+
+    assert!(matches!(neemo.update("missing", doc(json!({ "city": "LA" }))), Err(NeemoError::NotFound(_))));
+    assert_eq!(neemo.query("city", json!("LA")).unwrap().len(), 1);
+}
+
+#[test]
+fn overwriting_insert_drops_stale_index_entries() {
+    let (_dir, neemo) = open_temp();
+    neemo.create_index("tag").unwrap();
+    neemo.insert("a", doc(json!({ "tag": "old" }))).unwrap();
+    neemo.insert("a", doc(json!({ "tag": "new" }))).unwrap();
+
+    assert!(neemo.query("tag", json!("old")).unwrap().is_empty());
+    assert!(!neemo.exists_value("tag", json!("old")));
+    assert_eq!(neemo.query("tag", json!("new")).unwrap().len(), 1);
+}
+
+#[test]
+fn only_indexed_fields_populate_the_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let index = db.open_tree("index").unwrap();
+    let neemo = Neemo::with_stores(db.open_tree("docs").unwrap(), index.clone());
+    let entries = || index.iter().keys().flatten()
+        .filter(|key| !key.starts_with(b"\0"))
+        .map(|key| String::from_utf8_lossy(&key).into_owned())
+        .collect::<Vec<_>>();
+
+    neemo.create_index("email").unwrap();
+    neemo.insert("a", doc(json!({ "email": "ann@example.com", "name": "Ann" }))).unwrap();
+    neemo.insert("b", doc(json!({ "email": "bo@example.com", "name": "Bo" }))).unwrap();
+    assert_eq!(neemo.list_indexes(), vec!["email"]);
+    let indexed = entries();
+    assert_eq!(indexed.len(), 2);
+    assert!(indexed.iter().all(|key| key.starts_with("email:")));
+    // An unindexed field still answers queries by scanning.
+    assert_eq!(neemo.query("name", json!("Bo")).unwrap().len(), 1);
+
+    neemo.drop_index("email").unwrap();
+    assert!(neemo.list_indexes().is_empty());
+    assert!(entries().is_empty());
+    assert_eq!(neemo.query("email", json!("ann@example.com")).unwrap().len(), 1);
+}
+
+#[test]
+fn index_definitions_survive_reopening() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let neemo = Neemo::open(&db_path(&dir)).unwrap();
+        neemo.create_index("email").unwrap();
+        neemo.create_index("age").unwrap();
+        neemo.drop_index("age").unwrap();
+    }
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    assert_eq!(neemo.list_indexes(), vec!["email"]);
+}
+
+#[test]
+fn compact_index_removes_references_to_missing_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let index = db.open_tree("index").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), index.clone());
+    neemo.create_index("role").unwrap();
+    neemo.insert("a", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.insert("b", doc(json!({ "role": "admin" }))).unwrap();
+    neemo.insert("c", doc(json!({ "role": "guest" }))).unwrap();
+    // Remove documents behind the index's back, leaving dangling entries.
+    docs.remove("b").unwrap();
+    docs.remove("c").unwrap();
+    let entries = || index.iter().keys().flatten().filter(|key| !key.starts_with(b"\0")).count();
+    assert_eq!(entries(), 2);
+
+    assert_eq!(neemo.compact_index().unwrap(), 2);
+    assert_eq!(neemo.compact_index().unwrap(), 0);
+    assert_eq!(neemo.query_with_keys("role", json!("admin")).unwrap().into_iter().map(|(key, _)| key).collect::<Vec<_>>(), ["a"]);
+    assert!(!neemo.exists_value("role", json!("guest")));
+    assert_eq!(entries(), 1);
+}
+
+#[test]
+fn rebuild_index_repairs_a_tampered_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let index = db.open_tree("index").unwrap();
+    let text = db.open_tree("text").unwrap();
+    let neemo = Neemo::with_stores(db.open_tree("docs").unwrap(), index.clone()).with_text_index(text.clone());
+    neemo.create_index("role").unwrap();
+    neemo.insert("a", doc(json!({ "role": "admin", "bio": "likes tea" }))).unwrap();
+    neemo.insert("b", doc(json!({ "role": "guest", "bio": "likes coffee" }))).unwrap();
+    let keys = |role: &str| neemo.query_with_keys("role", json!(role)).unwrap().into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+    // Point each role's entry at the other document, and empty the text index.
+    let entries: Vec<_> = index.iter().flatten().filter(|(key, _)| !key.starts_with(b"\0")).collect();
+    assert_eq!(entries.len(), 2);
+    index.insert(&entries[0].0, entries[1].1.clone()).unwrap();
+    index.insert(&entries[1].0, entries[0].1.clone()).unwrap();
+    text.clear().unwrap();
+    assert_ne!(keys("admin"), ["a"]);
+    assert!(neemo.full_text_search("tea", None).unwrap().documents.is_empty());
+
+    neemo.rebuild_index().unwrap();
+    assert_eq!(keys("admin"), ["a"]);
+    assert_eq!(keys("guest"), ["b"]);
+    assert_eq!(neemo.list_indexes(), vec!["role"]);
+    assert_eq!(neemo.full_text_search("tea", None).unwrap().documents.len(), 1);
+    assert_eq!(neemo.full_text_search("likes", None).unwrap().documents.len(), 2);
+}
+
+#[test]
+fn delimiters_in_fields_and_values_do_not_collide() {
+    let neemo = Neemo::in_memory();
+    let fields = ["a", "a:b", "a\\", "tags", "tags\\*"];
+    for field in fields {
+        neemo.create_index(field).unwrap();
+    }
+    neemo.insert("x", doc(json!({ "a": "b:c" }))).unwrap();
+    neemo.insert("y", doc(json!({ "a:b": "c" }))).unwrap();
+    neemo.insert("z", doc(json!({ "a\\": ":b:c" }))).unwrap();
+    neemo.insert("t", doc(json!({ "tags": ["c"] }))).unwrap();
+    neemo.insert("u", doc(json!({ "tags\\*": "c" }))).unwrap();
+    let keys = |field: &str, value| {
+        let found = neemo.query_with_keys(field, value).unwrap();
+        found.into_iter().map(|(key, _)| key).collect::<Vec<_>>()
+    };
+
+    assert_eq!(keys("a", json!("b:c")), ["x"]);
+    assert_eq!(keys("a:b", json!("c")), ["y"]);
+    assert_eq!(keys("a\\", json!(":b:c")), ["z"]);
+    assert!(keys("a", json!("b")).is_empty());
+    assert_eq!(keys("tags\\*", json!("c")), ["u"]);
+    assert_eq!(neemo.query_contains("tags", json!("c")).len(), 1);
+    assert_eq!(neemo.distinct("a"), vec![json!("b:c")]);
+    assert_eq!(neemo.list_indexes().len(), fields.len());
+}
+
+#[test]
+fn query_contains_uses_one_entry_per_element() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0);
+    let rows = [
+        ("a", json!({ "name": "Ann", "tags": ["x", "y", "x"] })),
+        ("b", json!({ "name": "Bo", "tags": ["y"] })),
+        ("c", json!({ "name": "Cy", "tags": "x" })),
+        ("d", json!({ "name": "Di", "tags": [["x"], 1] })),
+    ];
+    for (key, value) in rows {
+        neemo.insert(key, doc(value)).unwrap();
+    }
+    let names = |element| values(&neemo.query_contains("tags", element), "name");
+    let scanned = (names(json!("x")), names(json!("y")), names(json!(1)));
+
+    neemo.create_index("tags").unwrap();
+    let (gets, scans) = (probe.gets.load(Ordering::SeqCst), probe.scans.load(Ordering::SeqCst));
+    assert_eq!(names(json!("x")), vec![json!("Ann")]);
+    assert_eq!(probe.gets.load(Ordering::SeqCst) - gets, 1);
+    assert_eq!(probe.scans.load(Ordering::SeqCst), scans);
+    assert_eq!(names(json!("y")), vec![json!("Ann"), json!("Bo")]);
+    assert_eq!(names(json!(1)), vec![json!("Di")]);
+    assert!(names(json!("z")).is_empty());
+    assert_eq!((names(json!("x")), names(json!("y")), names(json!(1))), scanned);
+
+    neemo.update("a", doc(json!({ "name": "Ann", "tags": ["z", "z"] }))).unwrap();
+    assert!(names(json!("x")).is_empty());
+    assert_eq!(names(json!("z")), vec![json!("Ann")]);
+    neemo.delete("b").unwrap();
+    assert!(names(json!("y")).is_empty());
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::doc;
+use neemo::{MemoryStore, Neemo};
+use serde_json::json;
+
+/// Stores users, orders and dated events under structured keys.
+fn keyed() -> Neemo<MemoryStore> {
+    let neemo = Neemo::in_memory();
+    for key in ["user:123", "user:456", "user:7", "users", "order:1", "event:2024-01-05", "event:2024-02-10", "event:2024-03-01"] {
+        neemo.insert(key, doc(json!({ "key": key }))).unwrap();
+    }
+    neemo
+}
+
+fn keys(found: Vec<(String, neemo::Document)>) -> Vec<String> {
+    found.into_iter().map(|(key, found)| {
+        assert_eq!(found.data["key"], json!(key));
+        key
+    }).collect()
+}
+
+#[test]
+fn get_prefix_returns_keys_starting_with_the_prefix() {
+    let neemo = keyed();
+    assert_eq!(keys(neemo.get_prefix("user:")), ["user:123", "user:456", "user:7"]);
+    assert_eq!(keys(neemo.get_prefix("user")), ["user:123", "user:456", "user:7", "users"]);
+    assert!(neemo.get_prefix("customer:").is_empty());
+    assert_eq!(neemo.get_prefix("").len(), 8);
+}
+
+#[test]
+fn scan_keys_includes_the_start_and_excludes_the_end() {
+    let neemo = keyed();
+    assert_eq!(keys(neemo.scan_keys("event:2024-01-05", "event:2024-03-01")), ["event:2024-01-05", "event:2024-02-10"]);
+    assert_eq!(keys(neemo.scan_keys("event:2024-01", "event:2024-02")), ["event:2024-01-05"]);
+    assert!(neemo.scan_keys("event:2025", "event:2026").is_empty());
+    assert!(neemo.scan_keys("user:9", "user:1").is_empty());
+    assert!(neemo.scan_keys("user:123", "user:123").is_empty());
+}
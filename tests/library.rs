@@ -0,0 +1,49 @@
+mod common;
+
+use common::{db_path, doc};
+use neemo::{Document, Neemo, NeemoError};
+use serde_json::json;
+
+#[test]
+fn documents_round_trip_through_json() {
+    let document = doc(json!({ "name": "Ann", "address": { "city": "Oslo" } }));
+    assert_eq!(document.get_path("address.city"), Some(&json!("Oslo")));
+    assert_eq!(document.get_path("address.zip"), None);
+
+    let text = serde_json::to_string(&document).unwrap();
+    let decoded: Document = serde_json::from_str(&text).unwrap();
+    assert_eq!(decoded, document);
+    assert_eq!(document.project(&["name"]), doc(json!({ "name": "Ann" })));
+}
+
+#[test]
+fn operations_report_outcomes_as_results() {
+    let neemo = Neemo::in_memory();
+    assert!(matches!(neemo.get_result("missing"), Ok(None)));
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    assert_eq!(neemo.get_result("a").unwrap(), Some(doc(json!({ "n": 1 }))));
+    neemo.delete("a").unwrap();
+    assert!(neemo.get("a").is_none());
+
+    let missing = neemo.update("a", doc(json!({ "n": 2 })));
+    assert!(matches!(missing, Err(NeemoError::NotFound(key)) if key == "a"));
+}
+
+#[test]
+fn a_database_opened_through_the_library_persists() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    {
+        let neemo = Neemo::open(&path).unwrap();
+        neemo.create_index("city").unwrap();
+        neemo.insert("a", doc(json!({ "city": "Oslo" }))).unwrap();
+        neemo.insert("b", doc(json!({ "city": "Rome" }))).unwrap();
+        assert_eq!(neemo.query("city", json!("Oslo")).unwrap().len(), 1);
+        neemo.close().unwrap();
+    }
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert_eq!(neemo.list_indexes(), ["city"]);
+    let found = neemo.query_with_keys("city", json!("Rome")).unwrap();
+    assert_eq!(found, [("b".to_string(), doc(json!({ "city": "Rome" })))]);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use std::sync::Mutex;
+
+use common::doc;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use neemo::Neemo;
+use serde_json::json;
+
+/// Collects the crate's debug lines so the test can inspect them.
+struct DebugLines(Mutex<Vec<String>>);
+
+impl Log for DebugLines {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Debug && metadata.target().starts_with("neemo")
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static DEBUG_LINES: DebugLines = DebugLines(Mutex::new(Vec::new()));
+
+#[test]
+fn operations_log_a_structured_debug_line() {
+    log::set_logger(&DEBUG_LINES).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let neemo = Neemo::in_memory();
+    neemo.create_index("city").unwrap();
+    neemo.insert("a", doc(json!({ "city": "Oslo" }))).unwrap();
+    neemo.insert("b", doc(json!({ "city": "Rome" }))).unwrap();
+    assert_eq!(neemo.query("city", json!("Oslo")).unwrap().len(), 1);
+    neemo.delete("b").unwrap();
+
+    let lines = DEBUG_LINES.0.lock().unwrap();
+    let query = lines.iter().find(|line| line.starts_with("op=query")).expect("no query line");
+    assert!(query.starts_with("op=query field=\"city\" count=1 scanned="), "{}", query);
+    assert!(query.contains(" elapsed_us="), "{}", query);
+    assert!(lines.iter().any(|line| line.starts_with("op=insert key=\"a\" count=1 ")));
+    assert!(lines.iter().any(|line| line.starts_with("op=delete key=\"b\" count=1 ")));
+}
@@ -0,0 +1,375 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+
+use common::{doc, values, ProbeStore};
+use neemo::{AccessMethod, Neemo, NullOrder, RangeEnd};
+use serde_json::{json, Value};
+
+#[test]
+fn query_sorted_multi_orders_by_each_key_in_turn() {
+    let neemo = Neemo::in_memory();
+    let rows = [
+        ("a", json!({ "kind": "x", "region": "west", "score": 5 })),
+        ("b", json!({ "kind": "x", "region": "east", "score": 3 })),
+        ("c", json!({ "kind": "x", "region": "west", "score": 9 })),
+        ("d", json!({ "kind": "x", "region": "east", "score": 7 })),
+        ("e", json!({ "kind": "x", "score": 1 })),
+        ("f", json!({ "kind": "y", "region": "east", "score": 100 })),
+    ];
+    for (key, value) in rows {
+        neemo.insert(key, doc(value)).unwrap();
+    }
+
+    let sort_keys = [("region".to_string(), false), ("score".to_string(), true)];
+    let sorted = neemo.query_sorted_multi("kind", json!("x"), &sort_keys).unwrap();
+    assert_eq!(values(&sorted, "score"), vec![json!(7), json!(3), json!(9), json!(5), json!(1)]);
+}
+
+#[test]
+fn query_detailed_reports_fewer_scanned_with_an_index() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("city").unwrap();
+    for i in 0..200 {
+        let city = if i % 50 == 0 { "Oslo" } else { "Rome" };
+        neemo.insert(&format!("u{:03}", i), doc(json!({ "city": city, "town": city }))).unwrap();
+    }
+
+    let indexed = neemo.query_detailed("city", json!("Oslo")).unwrap();
+    let scanned = neemo.query_detailed("town", json!("Oslo")).unwrap();
+    assert_eq!((indexed.matched, indexed.scanned), (4, 4));
+    assert_eq!((scanned.matched, scanned.scanned), (4, 200));
+    assert_eq!(indexed.documents.len(), indexed.matched);
+    assert_eq!(
+        indexed.documents.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+        scanned.documents.iter().map(|(key, _)| key).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn query_within_examines_only_the_given_keys() {
+    let neemo = Neemo::in_memory();
+    for (key, city) in [("a", "Oslo"), ("b", "Rome"), ("c", "Oslo"), ("d", "Rome"), ("e", "Lima"), ("f", "Oslo")] {
+        neemo.insert(key, doc(json!({ "city": city }))).unwrap();
+    }
+
+    let found = neemo.query_within(&["a", "b", "c", "d", "missing"], "city", json!("Oslo"));
+    assert_eq!(found.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+}
+
+/// Inserts numbers, a `null` and a document without the field.
+fn mixed_numbers() -> Neemo<neemo::MemoryStore> {
+    let neemo = Neemo::in_memory();
+    neemo.insert("five", doc(json!({ "kind": "x", "name": "five", "n": 5 }))).unwrap();
+    neemo.insert("null", doc(json!({ "kind": "x", "name": "null", "n": null }))).unwrap();
+    neemo.insert("missing", doc(json!({ "kind": "x", "name": "missing" }))).unwrap();
+    neemo.insert("one", doc(json!({ "kind": "x", "name": "one", "n": 1 }))).unwrap();
+    neemo.insert("three", doc(json!({ "kind": "x", "name": "three", "n": 3 }))).unwrap();
+    neemo
+}
+
+#[test]
+fn null_order_places_null_and_missing_fields() {
+    for indexed in [false, true] {
+        let neemo = mixed_numbers();
+        if indexed {
+            neemo.create_index("n").unwrap();
+        }
+        let names = |docs: Vec<neemo::Document>| values(&docs, "name");
+
+        let ascending = neemo.query_sorted("kind", json!("x"), "n", true).unwrap();
+        assert_eq!(names(ascending), vec![json!("one"), json!("three"), json!("five"), json!("null"), json!("missing")]);
+        let descending = neemo.query_sorted("kind", json!("x"), "n", false).unwrap();
+        assert_eq!(names(descending), vec![json!("five"), json!("three"), json!("one"), json!("null"), json!("missing")]);
+        let open_end = neemo.range_query("n", json!(2), Value::Null).unwrap();
+        assert_eq!(names(open_end), vec![json!("three"), json!("five"), json!("null"), json!("missing")]);
+        let open_start = neemo.range_query("n", Value::Null, json!(4)).unwrap();
+        assert_eq!(names(open_start), vec![json!("one"), json!("three")]);
+
+        neemo.set_null_order(NullOrder::First);
+        let ascending = neemo.query_sorted("kind", json!("x"), "n", true).unwrap();
+        assert_eq!(names(ascending), vec![json!("null"), json!("missing"), json!("one"), json!("three"), json!("five")]);
+        let open_start = neemo.range_query("n", Value::Null, json!(4)).unwrap();
+        assert_eq!(names(open_start), vec![json!("null"), json!("missing"), json!("one"), json!("three")]);
+        let open_end = neemo.range_query("n", json!(2), Value::Null).unwrap();
+        assert_eq!(names(open_end), vec![json!("three"), json!("five")]);
+    }
+}
+
+/// Inserts accounts with a mix of statuses and plans, indexing `status`.
+fn accounts() -> Neemo<neemo::MemoryStore> {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("status").unwrap();
+    for (key, status, plan) in [("a", "active", "pro"), ("b", "active", "free"), ("c", "closed", "pro"), ("d", "active", "pro")] {
+        neemo.insert(key, doc(json!({ "key": key, "status": status, "plan": plan }))).unwrap();
+    }
+    neemo
+}
+
+#[test]
+fn query_all_returns_documents_matching_every_condition() {
+    let neemo = accounts();
+    let keys = |conditions: &[(&str, Value)]| values(&neemo.query_all(conditions).unwrap(), "key");
+
+    assert_eq!(keys(&[("status", json!("active")), ("plan", json!("pro"))]), vec![json!("a"), json!("d")]);
+    assert_eq!(keys(&[("status", json!("active")), ("plan", json!("free"))]), vec![json!("b")]);
+    assert!(keys(&[("status", json!("closed")), ("plan", json!("free"))]).is_empty());
+    assert_eq!(keys(&[("plan", json!("pro"))]), vec![json!("a"), json!("c"), json!("d")]);
+    neemo.create_index("plan").unwrap();
+    assert_eq!(keys(&[("status", json!("active")), ("plan", json!("pro"))]), vec![json!("a"), json!("d")]);
+}
+
+#[test]
+fn query_any_returns_each_matching_document_once() {
+    let neemo = accounts();
+    let any = neemo.query_any(&[("status", json!("active")), ("plan", json!("pro"))]).unwrap();
+    assert_eq!(values(&any, "key"), vec![json!("a"), json!("b"), json!("c"), json!("d")]);
+
+    let none = neemo.query_any(&[("status", json!("gone")), ("plan", json!("gold"))]).unwrap();
+    assert!(none.is_empty());
+}
+
+#[test]
+fn paging_skips_offset_and_caps_at_limit() {
+    let neemo = accounts();
+    assert_eq!(values(&neemo.list_paged(1, 2), "key"), vec![json!("b"), json!("c")]);
+    assert_eq!(values(&neemo.list_paged(2, 10), "key"), vec![json!("c"), json!("d")]);
+    assert!(neemo.list_paged(10, 2).is_empty());
+
+    let active = |offset, limit| values(&neemo.query_paged("status", json!("active"), offset, limit).unwrap(), "key");
+    assert_eq!(active(1, 1), vec![json!("b")]);
+    assert_eq!(active(1, 10), vec![json!("b"), json!("d")]);
+    assert!(active(3, 10).is_empty());
+    let pro = |offset, limit| values(&neemo.query_paged("plan", json!("pro"), offset, limit).unwrap(), "key");
+    assert_eq!(pro(1, 10), vec![json!("c"), json!("d")]);
+    assert!(pro(5, 1).is_empty());
+}
+
+#[test]
+fn nested_paths_are_indexed_and_queried() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("address.city").unwrap();
+    neemo.create_index("address.geo.zone").unwrap();
+    neemo.create_index("tags").unwrap();
+    neemo.insert("a", doc(json!({ "name": "Ann", "address": { "city": "NYC", "geo": { "zone": "east" } }, "tags": ["vip", "new"] }))).unwrap();
+    neemo.insert("b", doc(json!({ "name": "Bo", "address": { "city": "LA", "geo": { "zone": "west" } }, "tags": ["new"] }))).unwrap();
+    neemo.insert("c", doc(json!({ "name": "Cy", "address": "NYC" }))).unwrap();
+
+    assert_eq!(values(&neemo.query("address.city", json!("NYC")).unwrap(), "name"), vec![json!("Ann")]);
+    assert_eq!(values(&neemo.query("address.geo.zone", json!("west")).unwrap(), "name"), vec![json!("Bo")]);
+    assert!(neemo.explain_query("address.geo.zone", json!("east")).indexed);
+    // An unindexed nested path is scanned and matches the same way.
+    assert_eq!(values(&neemo.query("address.geo", json!({ "zone": "east" })).unwrap(), "name"), vec![json!("Ann")]);
+    assert_eq!(values(&neemo.query_contains("tags", json!("new")), "name"), vec![json!("Ann"), json!("Bo")]);
+}
+
+#[test]
+fn numeric_ranges_follow_numeric_order() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("age").unwrap();
+    for age in 1..=100 {
+        neemo.insert(&format!("person:{age}"), doc(json!({ "age": age }))).unwrap();
+    }
+    let expected: Vec<Value> = (5..20).map(|age| json!(age)).collect();
+    assert_eq!(values(&neemo.range_query("age", json!(5), json!(20)).unwrap(), "age"), expected);
+
+    neemo.insert("fraction", doc(json!({ "age": 9.5 }))).unwrap();
+    neemo.insert("negative", doc(json!({ "age": -3 }))).unwrap();
+    assert_eq!(values(&neemo.range_query("age", json!(9), json!(10)).unwrap(), "age"), vec![json!(9), json!(9.5)]);
+    assert_eq!(values(&neemo.range_query("age", json!(-5), json!(2)).unwrap(), "age"), vec![json!(-3), json!(1)]);
+}
+
+#[test]
+fn range_end_decides_whether_the_end_value_is_returned() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("age").unwrap();
+    for age in [9, 10, 15, 20, 21] {
+        neemo.insert(&format!("person:{age}"), doc(json!({ "age": age }))).unwrap();
+    }
+    // Float spellings of the end value are included too.
+    neemo.insert("person:20.0", doc(json!({ "age": 20.0 }))).unwrap();
+
+    let excluded = neemo.range_query_with("age", json!(10), json!(20), RangeEnd::Excluded).unwrap();
+    assert_eq!(values(&excluded, "age"), vec![json!(10), json!(15)]);
+    assert_eq!(neemo.range_query("age", json!(10), json!(20)).unwrap(), excluded);
+    let included = neemo.range_query_with("age", json!(10), json!(20), RangeEnd::Included).unwrap();
+    assert_eq!(values(&included, "age").len(), 4);
+    assert_eq!(values(&included, "age")[..2], [json!(10), json!(15)]);
+
+    neemo.drop_index("age").unwrap();
+    let scanned = neemo.range_query_with("age", json!(10), json!(20), RangeEnd::Included).unwrap();
+    assert_eq!(scanned.len(), 4);
+}
+
+#[test]
+fn filter_applies_arbitrary_predicates() {
+    let neemo = Neemo::in_memory();
+    for (key, name, age) in [("a", "Alice", 34), ("b", "Adam", 25), ("c", "Bob", 41), ("d", "Amy", 31)] {
+        neemo.insert(key, doc(json!({ "name": name, "age": age }))).unwrap();
+    }
+    neemo.insert("e", doc(json!({ "name": 7 }))).unwrap();
+
+    let older = neemo.filter(|doc| doc.get_path("age").and_then(Value::as_i64).is_some_and(|age| age > 30));
+    assert_eq!(values(&older, "name"), vec![json!("Alice"), json!("Bob"), json!("Amy")]);
+    let starts_with_a = |doc: &neemo::Document| doc.get_path("name").and_then(Value::as_str).is_some_and(|name| name.starts_with('A'));
+    assert_eq!(values(&neemo.filter(starts_with_a), "name"), vec![json!("Alice"), json!("Adam"), json!("Amy")]);
+    let both = neemo.filter(|doc| starts_with_a(doc) && doc.get_path("age").and_then(Value::as_i64).is_some_and(|age| age > 30));
+    assert_eq!(values(&both, "name"), vec![json!("Alice"), json!("Amy")]);
+    assert!(neemo.filter(|_| false).is_empty());
+}
+
+#[test]
+fn comparison_queries_exclude_the_boundary() {
+    let neemo = Neemo::in_memory();
+    for (key, score) in [("a", json!(5)), ("b", json!(10)), ("c", json!(10.0)), ("d", json!(15)), ("e", json!(-2)), ("f", json!("10"))] {
+        neemo.insert(key, doc(json!({ "score": score }))).unwrap();
+    }
+    for indexed in [false, true] {
+        if indexed {
+            neemo.create_index("score").unwrap();
+        }
+        assert_eq!(values(&neemo.query_gt("score", json!(10)), "score"), vec![json!(15)], "indexed: {indexed}");
+        assert_eq!(values(&neemo.query_lt("score", json!(10)), "score"), vec![json!(-2), json!(5)], "indexed: {indexed}");
+        assert!(neemo.query_gt("score", json!(15)).is_empty());
+        assert_eq!(values(&neemo.query_lt("score", json!("2")), "score"), vec![json!("10")]);
+    }
+}
+
+#[test]
+fn list_sorted_orders_numbers_and_strings_with_missing_last() {
+    let neemo = Neemo::in_memory();
+    neemo.insert("a", doc(json!({ "name": "carol", "age": 10 }))).unwrap();
+    neemo.insert("b", doc(json!({ "name": "Alice", "age": 2 }))).unwrap();
+    neemo.insert("c", doc(json!({ "name": "bob", "age": 100 }))).unwrap();
+    neemo.insert("d", doc(json!({ "nickname": "dee" }))).unwrap();
+
+    assert_eq!(values(&neemo.list_sorted("age", true), "age"), vec![json!(2), json!(10), json!(100), Value::Null]);
+    assert_eq!(values(&neemo.list_sorted("age", false), "age"), vec![json!(100), json!(10), json!(2), Value::Null]);
+    assert_eq!(values(&neemo.list_sorted("name", true), "name"), vec![json!("Alice"), json!("bob"), json!("carol"), Value::Null]);
+    assert_eq!(values(&neemo.list_sorted("name", false), "name"), vec![json!("carol"), json!("bob"), json!("Alice"), Value::Null]);
+
+    neemo.insert("e", doc(json!({ "name": "eve", "age": 2, "team": "x" }))).unwrap();
+    neemo.insert("f", doc(json!({ "name": "fay", "age": 30, "team": "x" }))).unwrap();
+    let team = neemo.query_sorted("team", json!("x"), "name", false).unwrap();
+    assert_eq!(values(&team, "name"), vec![json!("fay"), json!("eve")]);
+}
+
+#[test]
+fn distinct_collapses_duplicates_in_value_order() {
+    let neemo = Neemo::in_memory();
+    for (key, city) in [("a", json!("Oslo")), ("b", json!("Lima")), ("c", json!("Oslo")), ("d", json!(7)), ("e", json!("Lima")), ("f", json!(7))] {
+        neemo.insert(key, doc(json!({ "city": city }))).unwrap();
+    }
+    neemo.insert("g", doc(json!({ "name": "no city" }))).unwrap();
+
+    let scanned = neemo.distinct("city");
+    assert_eq!(scanned, vec![json!(7), json!("Lima"), json!("Oslo")]);
+    // Repeated calls return the same order.
+    assert_eq!(neemo.distinct("city"), scanned);
+
+    neemo.create_index("city").unwrap();
+    assert_eq!(neemo.distinct("city"), scanned);
+    neemo.delete("b").unwrap();
+    neemo.delete("e").unwrap();
+    assert!(!neemo.distinct("city").contains(&json!("Lima")));
+}
+
+#[test]
+fn projections_keep_only_the_requested_fields() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("team").unwrap();
+    neemo.insert("a", doc(json!({ "team": "red", "name": "Ann", "bio": "long text", "address": { "city": "NYC", "zip": "10001" } }))).unwrap();
+    neemo.insert("b", doc(json!({ "team": "blue", "name": "Bo", "bio": "more text" }))).unwrap();
+
+    let red = neemo.query_projected("team", json!("red"), &["name", "address.city", "missing"]).unwrap();
+    assert_eq!(red.len(), 1);
+    let mut fields: Vec<&str> = red[0].data.keys().map(String::as_str).collect();
+    fields.sort();
+    assert_eq!(fields, ["address.city", "name"]);
+    assert_eq!(red[0].get_path("address.city"), Some(&json!("NYC")));
+
+    let listed = neemo.list_projected(&["name"]);
+    assert_eq!(listed.len(), 2);
+    assert!(listed.iter().all(|doc| doc.data.len() == 1 && !doc.data.contains_key("bio")));
+}
+
+#[test]
+fn explain_query_tells_an_index_lookup_from_a_scan() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("city").unwrap();
+    neemo.insert("a", doc(json!({ "city": "Oslo", "team": "red" }))).unwrap();
+    neemo.insert("b", doc(json!({ "city": "Oslo", "team": "blue" }))).unwrap();
+    neemo.insert("c", doc(json!({ "city": "Rome", "team": "red" }))).unwrap();
+
+    let indexed = neemo.explain_query("city", json!("Oslo"));
+    assert!(indexed.indexed);
+    assert_eq!(indexed.access, AccessMethod::IndexLookup);
+    assert_eq!(indexed.candidates, 2);
+    assert_eq!(indexed.to_string(), "index lookup on 'city': 2 candidate documents");
+    assert_eq!(neemo.explain_query("city", json!("Paris")).candidates, 0);
+
+    let scanned = neemo.explain_query("team", json!("red"));
+    assert!(!scanned.indexed);
+    assert_eq!(scanned.access, AccessMethod::FullScan);
+    assert_eq!(scanned.candidates, 3);
+    assert_eq!(scanned.to_string(), "full scan for unindexed 'team': 3 candidate documents");
+}
+
+fn players() -> Vec<(&'static str, Value)> {
+    vec![
+        ("a", json!({ "team": "red", "age": 25, "name": "Ann" })),
+        ("b", json!({ "team": "red", "age": 31, "name": "Bo" })),
+        ("c", json!({ "team": "blue", "age": 28, "name": "Cy" })),
+        ("d", json!({ "team": "red", "age": 40, "name": "Di" })),
+        ("e", json!({ "team": "red", "age": 19, "name": "Ed" })),
+        ("f", json!({ "team": "blue", "age": 35, "name": "Fa" })),
+    ]
+}
+
+#[test]
+fn query_builder_combines_conditions_sorting_and_paging() {
+    let neemo = Neemo::in_memory();
+    for (key, value) in players() {
+        neemo.insert(key, doc(value)).unwrap();
+    }
+    let run = || {
+        let found = neemo.query_builder()
+            .eq("team", json!("red"))
+            .range("age", json!(20), json!(40))
+            .sort_by("age", false)
+            .offset(1)
+            .limit(5)
+            .execute()
+            .unwrap();
+        values(&found, "name")
+    };
+
+    let scanned = run();
+    assert_eq!(scanned, vec![json!("Ann")]);
+    neemo.create_index("team").unwrap();
+    neemo.create_index("age").unwrap();
+    assert_eq!(run(), scanned);
+
+    let older = neemo.query_builder().gt("age", json!(27)).eq("team", json!("blue")).sort_by("name", true).execute().unwrap();
+    assert_eq!(values(&older, "name"), vec![json!("Cy"), json!("Fa")]);
+    let page = neemo.query_builder().gt("age", json!(20)).sort_by("age", true).offset(1).limit(2).execute().unwrap();
+    assert_eq!(values(&page, "name"), vec![json!("Cy"), json!("Bo")]);
+    assert!(neemo.query_builder().eq("team", json!("green")).execute().unwrap().is_empty());
+}
+
+#[test]
+fn query_builder_reads_only_indexed_candidates() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0);
+    neemo.create_index("team").unwrap();
+    for (key, value) in players() {
+        neemo.insert(key, doc(value)).unwrap();
+    }
+
+    let before = probe.gets.load(Ordering::SeqCst);
+    let scans = probe.scans.load(Ordering::SeqCst);
+    let found = neemo.query_builder().eq("team", json!("blue")).gt("age", json!(30)).execute().unwrap();
+    assert_eq!(values(&found, "name"), vec![json!("Fa")]);
+    assert_eq!(probe.gets.load(Ordering::SeqCst) - before, 2);
+    assert_eq!(probe.scans.load(Ordering::SeqCst), scans);
+}
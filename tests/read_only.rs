@@ -0,0 +1,63 @@
+mod common;
+
+use std::fs;
+
+use common::{db_path, doc, file_path};
+use neemo::{Neemo, NeemoError};
+use serde_json::json;
+
+#[test]
+fn writes_fail_and_leave_the_data_unchanged() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    let export = file_path(&dir, "export.json");
+    let backup = file_path(&dir, "backup");
+    {
+        let neemo = Neemo::open(&path).unwrap();
+        neemo.create_index("city").unwrap();
+        neemo.insert("a", doc(json!({ "city": "Oslo" }))).unwrap();
+        neemo.export(&export).unwrap();
+        neemo.backup(&backup).unwrap();
+    }
+
+    let neemo = Neemo::open_read_only(&path).unwrap();
+    assert!(matches!(neemo.insert("b", doc(json!({ "city": "Rome" }))), Err(NeemoError::ReadOnly)));
+    assert!(matches!(neemo.update("a", doc(json!({ "city": "Rome" }))), Err(NeemoError::ReadOnly)));
+    assert!(matches!(neemo.delete("a"), Err(NeemoError::ReadOnly)));
+    assert!(matches!(neemo.import(&export), Err(NeemoError::ReadOnly)));
+    assert!(matches!(neemo.restore(&backup), Err(NeemoError::ReadOnly)));
+    assert!(matches!(neemo.checkpoint(), Err(NeemoError::ReadOnly)));
+
+    assert_eq!(neemo.query("city", json!("Oslo")).unwrap().len(), 1);
+    assert!(neemo.get("b").is_none());
+    drop(neemo);
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert_eq!(neemo.get("a").unwrap().get_path("city"), Some(&json!("Oslo")));
+    assert_eq!(neemo.count().unwrap(), 1);
+}
+
+#[test]
+fn missing_side_stores_are_not_created() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    Neemo::open(&path).unwrap().insert("a", doc(json!({ "n": 1 }))).unwrap();
+    for tree in ["text", "expiry", "blobs", "history"] {
+        fs::remove_dir_all(dir.path().join("db").join(tree)).unwrap();
+    }
+
+    let neemo = Neemo::open_read_only(&path).unwrap();
+    assert_eq!(neemo.get("a").unwrap().get_path("n"), Some(&json!(1)));
+    assert!(neemo.history("a").is_empty());
+    drop(neemo);
+    for tree in ["text", "expiry", "blobs", "history"] {
+        assert!(!dir.path().join("db").join(tree).exists(), "{} was created", tree);
+    }
+}
+
+#[test]
+fn a_missing_database_is_not_created() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(Neemo::open_read_only(&db_path(&dir)).is_err());
+    assert!(!dir.path().join("db").exists());
+}
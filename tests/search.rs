@@ -0,0 +1,101 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+
+use common::{doc, open_temp, ProbeStore};
+use neemo::{KvStore, MemoryStore, Neemo, TextMatch};
+use serde_json::json;
+
+#[test]
+fn search_limit_truncates_results() {
+    let neemo = Neemo::in_memory();
+    for i in 0..12 {
+        neemo.insert(&format!("doc{:02}", i), doc(json!({ "title": format!("Rust note {}", i) }))).unwrap();
+    }
+
+    let limited = neemo.full_text_search("rust", Some(5)).unwrap();
+    assert_eq!(limited.documents.len(), 5);
+    assert!(limited.truncated);
+
+    let all = neemo.full_text_search("rust", None).unwrap();
+    assert_eq!(all.documents.len(), 12);
+    assert!(!all.truncated);
+}
+
+#[test]
+fn search_limit_equal_to_matches_is_not_truncated() {
+    let (_dir, neemo) = open_temp();
+    for i in 0..5 {
+        neemo.insert(&format!("doc{}", i), doc(json!({ "body": "needle" }))).unwrap();
+    }
+    neemo.insert("other", doc(json!({ "body": "haystack" }))).unwrap();
+
+    let results = neemo.full_text_search("needle", Some(5)).unwrap();
+    assert_eq!(results.documents.len(), 5);
+    assert!(!results.truncated);
+}
+
+/// Returns the `id` of each document found by a search.
+fn found<S: KvStore>(neemo: &Neemo<S>, query: &str, mode: TextMatch) -> Vec<serde_json::Value> {
+    let results = neemo.full_text_search_with(query, None, mode).unwrap();
+    results.documents.iter().map(|doc| doc.data["id"].clone()).collect()
+}
+
+/// Checks case folding, nested values, arrays and whole-word matching.
+fn check_text_matching<S: KvStore>(neemo: Neemo<S>) {
+    neemo.insert("a", doc(json!({ "id": "a", "title": "Learning Rust" }))).unwrap();
+    neemo.insert("b", doc(json!({ "id": "b", "meta": { "author": { "name": "Trusty Crab" } } }))).unwrap();
+    neemo.insert("c", doc(json!({ "id": "c", "tags": ["systems", "RUST lang"] }))).unwrap();
+    neemo.insert("d", doc(json!({ "id": "d", "title": "Go", "count": 3 }))).unwrap();
+
+    assert_eq!(found(&neemo, "RUST", TextMatch::Substring), vec![json!("a"), json!("b"), json!("c")]);
+    assert_eq!(found(&neemo, "rust", TextMatch::WholeWord), vec![json!("a"), json!("c")]);
+    assert_eq!(found(&neemo, "crab", TextMatch::WholeWord), vec![json!("b")]);
+    assert_eq!(found(&neemo, "Rust Lang", TextMatch::WholeWord), vec![json!("c")]);
+    assert_eq!(found(&neemo, "lang rust", TextMatch::WholeWord), Vec::<serde_json::Value>::new());
+    assert_eq!(found(&neemo, "SYSTEMS", TextMatch::Substring), vec![json!("c")]);
+}
+
+#[test]
+fn search_is_case_insensitive_and_reaches_nested_values() {
+    check_text_matching(Neemo::in_memory());
+    check_text_matching(Neemo::with_stores(MemoryStore::new(), MemoryStore::new()));
+}
+
+#[test]
+fn text_index_reads_only_matching_documents() {
+    let (store, probe) = ProbeStore::new();
+    let neemo = Neemo::with_stores(store, ProbeStore::new().0).with_text_index(ProbeStore::new().0);
+    for i in 0..100 {
+        let animal = if i == 17 || i == 60 { "zebra" } else { "horse" };
+        neemo.insert(&format!("doc{:03}", i), doc(json!({ "id": i, "text": format!("a striped {}", animal) }))).unwrap();
+    }
+    let reads = || (probe.gets.load(Ordering::SeqCst), probe.scans.load(Ordering::SeqCst));
+
+    let (gets, scans) = reads();
+    assert_eq!(found(&neemo, "Zebra", TextMatch::WholeWord), vec![json!(17), json!(60)]);
+    assert_eq!(reads(), (gets + 2, scans));
+
+    neemo.delete("doc017").unwrap();
+    let (gets, scans) = reads();
+    assert_eq!(found(&neemo, "zebra", TextMatch::Substring), vec![json!(60)]);
+    assert_eq!(reads(), (gets + 1, scans));
+}
+
+#[test]
+fn ranked_search_puts_more_mentions_first() {
+    let neemo = Neemo::in_memory();
+    neemo.insert("once", doc(json!({ "id": "once", "text": "the cat sat" }))).unwrap();
+    neemo.insert("twice", doc(json!({ "id": "twice", "text": "cat eats; cat sleeps" }))).unwrap();
+    neemo.insert("both", doc(json!({ "id": "both", "text": "a cat and a dog" }))).unwrap();
+    neemo.insert("none", doc(json!({ "id": "none", "text": "a bird" }))).unwrap();
+
+    let ranked = neemo.full_text_search_ranked("cat");
+    let ids: Vec<_> = ranked.iter().map(|(doc, _)| doc.data["id"].clone()).collect();
+    assert_eq!(ids, vec![json!("twice"), json!("both"), json!("once")]);
+    assert!(ranked[0].1 > ranked[1].1);
+
+    let ranked = neemo.full_text_search_ranked("cat dog");
+    assert_eq!(ranked[0].0.data["id"], json!("both"));
+    assert_eq!(ranked.len(), 3);
+}
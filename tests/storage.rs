@@ -0,0 +1,186 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{db_path, doc, open_temp};
+use neemo::{Neemo, NeemoError};
+use serde_json::json;
+
+#[test]
+fn raw_iter_yields_records_that_are_not_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap());
+    neemo.insert("good", doc(json!({ "n": 1 }))).unwrap();
+    docs.insert("bad", &b"\xffnot json"[..]).unwrap();
+
+    let raw: Vec<(String, Vec<u8>)> = neemo.raw_iter().collect::<Result<_, _>>().unwrap();
+    assert_eq!(raw.len(), 2);
+    assert_eq!(raw[0], ("bad".to_string(), b"\xffnot json".to_vec()));
+    assert_eq!(raw[1].0, "good");
+    assert_eq!(serde_json::from_slice::<serde_json::Value>(&raw[1].1).unwrap(), json!({ "data": { "n": 1 } }));
+
+    let typed: Vec<_> = neemo.iter_documents().collect();
+    assert!(matches!(&typed[0], Err(NeemoError::Corrupt(key)) if key == "bad"));
+    assert!(matches!(&typed[1], Ok((key, _)) if key == "good"));
+}
+
+#[test]
+fn count_matches_list_and_leaves_out_expired_documents() {
+    let (_dir, neemo) = open_temp();
+    neemo.create_index("status").unwrap();
+    for key in ["a", "b", "c"] {
+        neemo.insert(key, doc(json!({ "status": "active" }))).unwrap();
+    }
+    neemo.insert_with_ttl("d", doc(json!({ "status": "active" })), Duration::from_millis(1)).unwrap();
+    neemo.insert_with_ttl("e", doc(json!({ "status": "active" })), Duration::from_secs(3600)).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(neemo.count().unwrap(), 4);
+    assert_eq!(neemo.count().unwrap(), neemo.list().len());
+    assert_eq!(neemo.count_query("status", json!("active")).unwrap(), 4);
+    assert_eq!(neemo.count_query("status", json!("active")).unwrap(), neemo.query("status", json!("active")).unwrap().len());
+}
+
+#[test]
+fn iter_documents_can_stop_after_the_first_few() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap());
+    let batch: Vec<_> = (0..1_000).map(|i| (format!("doc:{i:04}"), doc(json!({ "n": i })))).collect();
+    neemo.insert_many(&batch).unwrap();
+    // Only a full pass reaches this record and fails to decode it.
+    docs.insert("doc:9999", &b"not json"[..]).unwrap();
+
+    let first: Vec<(String, _)> = neemo.iter_documents().take(5).collect::<Result<_, _>>().unwrap();
+    let keys: Vec<&str> = first.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["doc:0000", "doc:0001", "doc:0002", "doc:0003", "doc:0004"]);
+    assert_eq!(first[4].1.data["n"], json!(4));
+
+    assert!(neemo.iter_documents().any(|item| matches!(item, Err(NeemoError::Corrupt(_)))));
+    assert_eq!(neemo.list().len(), 1_000);
+}
+
+#[test]
+fn contains_key_checks_presence_without_decoding() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap());
+    neemo.insert("present", doc(json!({ "n": 1 }))).unwrap();
+    neemo.insert("deleted", doc(json!({ "n": 2 }))).unwrap();
+    neemo.delete("deleted").unwrap();
+    docs.insert("undecodable", &b"not json"[..]).unwrap();
+
+    assert!(neemo.contains_key("present"));
+    assert!(!neemo.contains_key("absent"));
+    assert!(!neemo.contains_key("deleted"));
+    assert!(neemo.contains_key("undecodable"));
+    assert!(matches!(neemo.get_result("undecodable"), Err(NeemoError::Corrupt(_))));
+}
+
+#[test]
+fn reads_skip_corrupt_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap())
+        .with_text_index(db.open_tree("text").unwrap());
+    neemo.create_index("role").unwrap();
+    neemo.insert("a", doc(json!({ "role": "admin", "age": 30, "bio": "keeps bees" }))).unwrap();
+    neemo.insert("b", doc(json!({ "role": "admin", "age": 40, "bio": "keeps goats" }))).unwrap();
+    // `b` stays in the indexes while its stored bytes are garbage.
+    docs.insert("b", &b"\x00\xffgarbage"[..]).unwrap();
+    docs.insert("c", &b"{not json"[..]).unwrap();
+
+    assert_eq!(neemo.list().len(), 1);
+    assert_eq!(neemo.query("role", json!("admin")).unwrap().len(), 1);
+    assert_eq!(neemo.query("age", json!(30)).unwrap().len(), 1);
+    assert_eq!(neemo.range_query("age", json!(0), json!(100)).unwrap().len(), 1);
+    assert_eq!(neemo.aggregate("age", "sum").unwrap(), Some(json!(30.0)));
+    assert_eq!(neemo.full_text_search("keeps", None).unwrap().documents.len(), 1);
+    assert!(neemo.get("b").is_none());
+}
+
+#[test]
+fn stats_count_documents_and_index_entries() {
+    let (_dir, neemo) = open_temp();
+    let empty = neemo.stats().unwrap();
+    assert_eq!((empty.documents, empty.index_entries), (0, 0));
+
+    neemo.create_index("role").unwrap();
+    neemo.insert("a", doc(json!({ "role": "admin", "name": "Ann" }))).unwrap();
+    neemo.insert("b", doc(json!({ "role": "admin", "name": "Bo" }))).unwrap();
+    neemo.insert("c", doc(json!({ "role": "guest", "name": "Cy" }))).unwrap();
+    neemo.insert_with_ttl("d", doc(json!({ "role": "admin", "name": "Di" })), Duration::ZERO).unwrap();
+    neemo.flush().unwrap();
+
+    let stats = neemo.stats().unwrap();
+    assert_eq!(stats.documents, 3);
+    assert_eq!(stats.documents, neemo.count().unwrap());
+    assert_eq!(stats.index_entries, 2);
+    assert!(stats.data_size > 0 && stats.index_size > 0);
+    let shown = stats.to_string();
+    assert!(shown.starts_with("3 documents, 2 index entries, "), "{shown}");
+
+    neemo.delete("c").unwrap();
+    assert_eq!(neemo.stats().unwrap().index_entries, 1);
+}
+
+#[test]
+fn get_many_keeps_the_order_of_the_keys() {
+    let neemo = Neemo::in_memory().with_document_cache(4);
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    neemo.insert("c", doc(json!({ "n": 3 }))).unwrap();
+    neemo.insert_with_ttl("d", doc(json!({ "n": 4 })), Duration::ZERO).unwrap();
+    neemo.get("a");
+
+    let found = neemo.get_many(&["c", "missing", "a", "d", "c"]);
+    let expected = [Some(doc(json!({ "n": 3 }))), None, Some(doc(json!({ "n": 1 }))), None, Some(doc(json!({ "n": 3 })))];
+    assert_eq!(found, expected);
+    assert!(neemo.get_many(&[]).is_empty());
+}
+
+#[test]
+fn clear_empties_the_database_but_keeps_its_indexes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = db_path(&dir);
+    {
+        let neemo = Neemo::open(&path).unwrap();
+        neemo.create_index("city").unwrap();
+        neemo.insert("a", doc(json!({ "city": "Oslo", "bio": "likes fjords" }))).unwrap();
+        neemo.insert_with_ttl("b", doc(json!({ "city": "Oslo" })), Duration::from_secs(3600)).unwrap();
+        neemo.insert_blob("a", "photo", b"jpeg").unwrap();
+
+        neemo.clear().unwrap();
+        assert_eq!(neemo.count().unwrap(), 0);
+        assert!(neemo.query("city", json!("Oslo")).unwrap().is_empty());
+        assert!(neemo.full_text_search("fjords", None).unwrap().documents.is_empty());
+        assert_eq!(neemo.get_blob("a", "photo").unwrap(), None);
+        assert!(neemo.list().is_empty());
+    }
+
+    let neemo = Neemo::open(&path).unwrap();
+    assert_eq!(neemo.count().unwrap(), 0);
+    assert_eq!(neemo.list_indexes(), ["city"]);
+    neemo.insert("c", doc(json!({ "city": "Oslo" }))).unwrap();
+    assert_eq!(neemo.query("city", json!("Oslo")).unwrap().len(), 1);
+}
+
+#[test]
+fn list_paged_skips_corrupt_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap());
+    for key in ["a", "c", "d"] {
+        neemo.insert(key, doc(json!({ "key": key }))).unwrap();
+    }
+    docs.insert("b", b"not json".to_vec()).unwrap();
+
+    assert_eq!(neemo.list_paged(1, 2), [doc(json!({ "key": "c" })), doc(json!({ "key": "d" }))]);
+    assert_eq!(neemo.list_paged(0, 2), [doc(json!({ "key": "a" })), doc(json!({ "key": "c" }))]);
+}
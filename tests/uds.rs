@@ -0,0 +1,48 @@
+#![cfg(all(unix, feature = "uds"))]
+
+mod common;
+
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+
+use common::{doc, file_path};
+use neemo::uds::{UdsClient, UdsServer};
+use neemo::Neemo;
+use serde_json::json;
+
+#[test]
+fn client_round_trip_and_socket_removed_on_stop() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "neemo.sock");
+    let neemo = Arc::new(Neemo::in_memory());
+    let server = UdsServer::start(Arc::clone(&neemo), &path).unwrap();
+
+    let mut client = UdsClient::connect(&path).unwrap();
+    client.insert("a", doc(json!({ "city": "Oslo" }))).unwrap();
+    assert_eq!(client.get("a").unwrap().unwrap().data["city"], json!("Oslo"));
+    assert_eq!(client.query("city", json!("Oslo")).unwrap().len(), 1);
+    assert!(neemo.get("a").is_some());
+    client.delete("a").unwrap();
+    assert_eq!(client.get("a").unwrap(), None);
+
+    server.stop();
+    assert!(!std::path::Path::new(&path).exists());
+    assert!(client.get("a").is_err());
+}
+
+#[test]
+fn start_replaces_a_stale_socket_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = file_path(&dir, "neemo.sock");
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(std::path::Path::new(&path).exists());
+
+    let server = UdsServer::start(Arc::new(Neemo::in_memory()), &path).unwrap();
+    let mut client = UdsClient::connect(&path).unwrap();
+    assert_eq!(client.get("missing").unwrap(), None);
+
+    let second = UdsServer::start(Arc::new(Neemo::in_memory()), &path);
+    assert_eq!(second.err().map(|e| e.kind()), Some(std::io::ErrorKind::AddrInUse));
+    drop(server);
+    assert!(!std::path::Path::new(&path).exists());
+}
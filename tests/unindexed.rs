@@ -0,0 +1,47 @@
+mod common;
+
+use std::sync::Mutex;
+
+use common::doc;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use neemo::Neemo;
+use serde_json::json;
+
+/// Collects warnings so the test can count them.
+struct Warnings(Mutex<Vec<String>>);
+
+impl Log for Warnings {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static WARNINGS: Warnings = Warnings(Mutex::new(Vec::new()));
+
+#[test]
+fn unindexed_query_warns_once_and_is_reported() {
+    log::set_logger(&WARNINGS).unwrap();
+    log::set_max_level(LevelFilter::Warn);
+
+    let neemo = Neemo::in_memory();
+    neemo.create_index("city").unwrap();
+    neemo.insert("a", doc(json!({ "city": "Oslo", "nickname": "ace" }))).unwrap();
+
+    assert_eq!(neemo.query("nickname", json!("ace")).unwrap().len(), 1);
+    assert_eq!(neemo.query("nickname", json!("ace")).unwrap().len(), 1);
+    assert_eq!(neemo.query("city", json!("Oslo")).unwrap().len(), 1);
+
+    let warnings = WARNINGS.0.lock().unwrap();
+    let about_nickname = warnings.iter().filter(|message| message.contains("'nickname'")).count();
+    assert_eq!(about_nickname, 1, "warnings: {:?}", warnings);
+    assert!(!warnings.iter().any(|message| message.contains("'city'")));
+    assert_eq!(neemo.unindexed_query_fields(), vec!["nickname".to_string()]);
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use std::thread;
+
+use common::{doc, open_temp};
+use neemo::ChangeEvent;
+use serde_json::json;
+
+#[test]
+fn watchers_receive_changes_under_their_prefix() {
+    let (_dir, neemo) = open_temp();
+    let mut events = neemo.watch("user:");
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            neemo.insert("order:1", doc(json!({ "total": 5 }))).unwrap();
+            neemo.insert("user:a", doc(json!({ "name": "Ann" }))).unwrap();
+            neemo.delete("order:1").unwrap();
+            neemo.delete("user:a").unwrap();
+        });
+    });
+
+    assert_eq!(events.next(), Some(ChangeEvent::Insert { key: "user:a".to_string(), doc: doc(json!({ "name": "Ann" })) }));
+    assert_eq!(events.next(), Some(ChangeEvent::Delete { key: "user:a".to_string() }));
+}
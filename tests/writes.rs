@@ -0,0 +1,303 @@
+mod common;
+
+use std::sync::Barrier;
+use std::thread;
+use std::time::Duration;
+
+use common::{db_path, doc, open_temp};
+use neemo::{BatchOp, FieldType, KvStore, Neemo, NeemoError, Schema, UpsertResult};
+use serde_json::json;
+
+#[test]
+fn insert_doc_keys_documents_by_id_field() {
+    let neemo = Neemo::in_memory();
+    neemo.set_id_field("id");
+
+    assert_eq!(neemo.insert_doc(doc(json!({ "id": "alice", "age": 30 }))).unwrap(), "alice");
+    assert_eq!(neemo.insert_doc(doc(json!({ "id": 7, "age": 41 }))).unwrap(), "7");
+    assert_eq!(neemo.get("alice").unwrap().data["age"], json!(30));
+    assert_eq!(neemo.get("7").unwrap().data["age"], json!(41));
+
+    let duplicate = neemo.insert_doc(doc(json!({ "id": "alice", "age": 99 })));
+    assert!(matches!(duplicate, Err(NeemoError::DuplicateKey(key)) if key == "alice"));
+    assert_eq!(neemo.get("alice").unwrap().data["age"], json!(30));
+
+    let missing = neemo.insert_doc(doc(json!({ "age": 5 })));
+    assert!(matches!(missing, Err(NeemoError::MissingIdField(field)) if field == "id"));
+}
+
+#[test]
+fn insert_doc_requires_an_id_field() {
+    let neemo = Neemo::in_memory();
+    let result = neemo.insert_doc(doc(json!({ "id": "alice" })));
+    assert!(matches!(result, Err(NeemoError::IdFieldNotSet)));
+}
+
+#[test]
+fn swap_exchanges_documents_and_their_index_entries() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("slot").unwrap();
+    neemo.insert("a", doc(json!({ "slot": "first", "name": "Ann" }))).unwrap();
+    neemo.insert("b", doc(json!({ "slot": "second", "name": "Bo" }))).unwrap();
+
+    neemo.swap("a", "b").unwrap();
+    assert_eq!(neemo.get("a").unwrap().data["name"], json!("Bo"));
+    assert_eq!(neemo.get("b").unwrap().data["name"], json!("Ann"));
+    let first = neemo.query_with_keys("slot", json!("first")).unwrap();
+    assert_eq!(first.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["b"]);
+    let second = neemo.query_with_keys("slot", json!("second")).unwrap();
+    assert_eq!(second.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), vec!["a"]);
+
+    assert!(matches!(neemo.swap("a", "missing"), Err(NeemoError::NotFound(key)) if key == "missing"));
+    assert_eq!(neemo.get("a").unwrap().data["name"], json!("Bo"));
+}
+
+/// Runs a transaction that fails after writing, then one that succeeds, and
+/// checks the documents, index and text index after each.
+fn check_transaction<S: KvStore>(neemo: Neemo<S>) {
+    neemo.create_index("role").unwrap();
+    neemo.set_schema(Schema::new("user:").require("role", FieldType::String));
+    neemo.insert("user:a", doc(json!({ "role": "admin", "bio": "first user" }))).unwrap();
+    let admins = |neemo: &Neemo<S>| neemo.query_with_keys("role", json!("admin")).unwrap().into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+    let failed: Result<(), NeemoError> = neemo.transaction(|tx| {
+        tx.insert("user:b", doc(json!({ "role": "admin", "bio": "second user" })))?;
+        assert!(tx.delete("user:a")?);
+        assert!(tx.get("user:a")?.is_none());
+        Err(NeemoError::Cancelled)
+    });
+    assert!(matches!(failed, Err(NeemoError::Cancelled)));
+    assert!(neemo.get("user:b").is_none());
+    assert!(neemo.get("user:a").is_some());
+    assert_eq!(admins(&neemo), vec!["user:a"]);
+    assert!(neemo.full_text_search("second", None).unwrap().documents.is_empty());
+
+    let invalid = neemo.transaction(|tx| tx.insert("user:c", doc(json!({ "role": 7 }))));
+    assert!(matches!(invalid, Err(NeemoError::Validation(_))));
+    assert!(neemo.get("user:c").is_none());
+
+    let moved = neemo.transaction(|tx| {
+        let mut doc = tx.get("user:a")?.unwrap();
+        doc.data.insert("bio".to_string(), json!("moved user"));
+        tx.insert("user:b", doc)?;
+        tx.delete("user:a")
+    });
+    assert!(moved.unwrap());
+    assert!(neemo.get("user:a").is_none());
+    assert_eq!(admins(&neemo), vec!["user:b"]);
+    let found = neemo.full_text_search("moved", None).unwrap().documents;
+    assert_eq!(found.len(), 1);
+    assert!(neemo.full_text_search("first", None).unwrap().documents.is_empty());
+}
+
+#[test]
+fn transactions_commit_all_or_nothing() {
+    check_transaction(Neemo::in_memory());
+
+    let (_dir, on_disk) = open_temp();
+    check_transaction(on_disk);
+
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let shared = Neemo::with_stores(db.open_tree("docs").unwrap(), db.open_tree("index").unwrap())
+        .with_text_index(db.open_tree("text").unwrap());
+    check_transaction(shared);
+}
+
+#[test]
+fn insert_many_keeps_index_and_text_consistent() {
+    let dir = tempfile::tempdir().unwrap();
+    let keys = |neemo: &Neemo, role: &str| neemo.query_with_keys("role", json!(role)).unwrap().into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+    {
+        let neemo = Neemo::open(&db_path(&dir)).unwrap();
+        neemo.create_index("role").unwrap();
+        neemo.insert("a", doc(json!({ "role": "admin", "bio": "original" }))).unwrap();
+        neemo.insert_many(&[
+            ("a".to_string(), doc(json!({ "role": "user", "bio": "interim" }))),
+            ("b".to_string(), doc(json!({ "role": "admin", "bio": "second" }))),
+            ("a".to_string(), doc(json!({ "role": "guest", "bio": "final" }))),
+        ]).unwrap();
+    }
+
+    let neemo = Neemo::open(&db_path(&dir)).unwrap();
+    assert_eq!(neemo.get("a").unwrap().data["bio"], json!("final"));
+    assert_eq!(keys(&neemo, "admin"), vec!["b"]);
+    assert_eq!(keys(&neemo, "guest"), vec!["a"]);
+    assert!(keys(&neemo, "user").is_empty());
+    for (word, expected) in [("original", 0), ("interim", 0), ("final", 1), ("second", 1)] {
+        assert_eq!(neemo.full_text_search(word, None).unwrap().documents.len(), expected, "{word}");
+    }
+}
+
+#[test]
+fn schemas_reject_missing_fields_and_wrong_types() {
+    let neemo = Neemo::in_memory();
+    neemo.insert("user:legacy", doc(json!({ "anything": true }))).unwrap();
+    neemo.set_schema(Schema::new("user:").require("name", FieldType::String).require("address.zip", FieldType::Number));
+
+    let missing = neemo.insert("user:a", doc(json!({ "address": { "zip": 10001 } })));
+    assert!(matches!(missing, Err(NeemoError::Validation(message)) if message.contains("missing required field 'name'")));
+    let mismatch = neemo.insert("user:a", doc(json!({ "name": "Ann", "address": { "zip": "10001" } })));
+    assert!(matches!(mismatch, Err(NeemoError::Validation(message)) if message.contains("'address.zip'")));
+    assert!(neemo.get("user:a").is_none());
+
+    neemo.insert("user:a", doc(json!({ "name": "Ann", "address": { "zip": 10001 } }))).unwrap();
+    assert!(matches!(neemo.update("user:a", doc(json!({ "name": 7, "address": { "zip": 1 } }))), Err(NeemoError::Validation(_))));
+    assert!(matches!(neemo.patch("user:a", doc(json!({ "name": null }))), Err(NeemoError::Validation(_))));
+    assert_eq!(neemo.get("user:a").unwrap().data["name"], json!("Ann"));
+
+    // Keys outside the prefix, and documents stored before, are not checked.
+    neemo.insert("order:1", doc(json!({ "total": 5 }))).unwrap();
+    assert!(neemo.get("user:legacy").is_some());
+}
+
+#[test]
+fn compare_and_swap_lets_only_one_racing_update_win() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("owner").unwrap();
+    assert!(neemo.compare_and_swap("task", None, Some(doc(json!({ "owner": "nobody" })))).unwrap());
+    assert!(!neemo.compare_and_swap("task", None, Some(doc(json!({ "owner": "late" })))).unwrap());
+
+    // Both updaters read the same version, then race to claim the task.
+    let seen = neemo.get("task").unwrap();
+    let barrier = Barrier::new(2);
+    let wins: Vec<bool> = thread::scope(|scope| {
+        let racers: Vec<_> = ["ann", "bo"].into_iter().map(|owner| {
+            let (neemo, seen, barrier) = (&neemo, &seen, &barrier);
+            scope.spawn(move || {
+                barrier.wait();
+                neemo.compare_and_swap("task", Some(seen), Some(doc(json!({ "owner": owner })))).unwrap()
+            })
+        }).collect();
+        racers.into_iter().map(|racer| racer.join().unwrap()).collect()
+    });
+    assert_eq!(wins.iter().filter(|won| **won).count(), 1);
+
+    let winner = if wins[0] { "ann" } else { "bo" };
+    let loser = if wins[0] { "bo" } else { "ann" };
+    assert_eq!(neemo.get("task").unwrap().data["owner"], json!(winner));
+    assert_eq!(neemo.query("owner", json!(winner)).unwrap().len(), 1);
+    assert!(neemo.query("owner", json!(loser)).unwrap().is_empty());
+    assert!(neemo.query("owner", json!("nobody")).unwrap().is_empty());
+
+    // A stale delete fails too; a current one removes the index entry.
+    assert!(!neemo.compare_and_swap("task", Some(&seen), None).unwrap());
+    let current = neemo.get("task").unwrap();
+    assert!(neemo.compare_and_swap("task", Some(&current), None).unwrap());
+    assert!(neemo.get("task").is_none());
+    assert!(neemo.query("owner", json!(winner)).unwrap().is_empty());
+}
+
+#[test]
+fn swap_refuses_documents_that_break_the_other_key_schema() {
+    let neemo = Neemo::in_memory();
+    neemo.set_schema(Schema::new("user:").require("name", FieldType::String));
+    neemo.insert("user:a", doc(json!({ "name": "Ann" }))).unwrap();
+    neemo.insert("draft:b", doc(json!({ "title": "untitled" }))).unwrap();
+
+    assert!(matches!(neemo.swap("user:a", "draft:b"), Err(NeemoError::Validation(_))));
+    assert_eq!(neemo.get("user:a").unwrap().data["name"], json!("Ann"));
+    assert_eq!(neemo.get("draft:b").unwrap().data["title"], json!("untitled"));
+}
+
+#[test]
+fn merge_adds_overwrites_and_deep_merges_fields() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("status").unwrap();
+    neemo.create_index("address.city").unwrap();
+    neemo.merge("a", doc(json!({ "status": "new", "address": { "city": "NYC", "zip": "10001" } }))).unwrap();
+    assert_eq!(neemo.get("a").unwrap().data["status"], json!("new"));
+
+    neemo.merge("a", doc(json!({ "plan": "pro" }))).unwrap();
+    neemo.merge("a", doc(json!({ "status": "active" }))).unwrap();
+    neemo.merge("a", doc(json!({ "address": { "city": "LA", "geo": { "lat": 34 } } }))).unwrap();
+
+    let merged = neemo.get("a").unwrap();
+    assert_eq!(merged.data["plan"], json!("pro"));
+    assert_eq!(merged.data["status"], json!("active"));
+    assert_eq!(merged.data["address"], json!({ "city": "LA", "zip": "10001", "geo": { "lat": 34 } }));
+
+    assert!(neemo.query("status", json!("new")).unwrap().is_empty());
+    assert_eq!(neemo.query("status", json!("active")).unwrap().len(), 1);
+    assert!(neemo.query("address.city", json!("NYC")).unwrap().is_empty());
+    assert_eq!(neemo.query("address.city", json!("LA")).unwrap().len(), 1);
+
+    // A non-object value replaces an object outright.
+    neemo.merge("a", doc(json!({ "address": "unknown" }))).unwrap();
+    assert_eq!(neemo.get("a").unwrap().data["address"], json!("unknown"));
+    assert!(neemo.query("address.city", json!("LA")).unwrap().is_empty());
+}
+
+#[test]
+fn batch_writes_are_indexed() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("role").unwrap();
+    neemo.insert("old", doc(json!({ "role": "admin" }))).unwrap();
+    let ops = [
+        BatchOp::Insert { key: "a".to_string(), doc: doc(json!({ "role": "admin" })) },
+        BatchOp::Insert { key: "b".to_string(), doc: doc(json!({ "role": "guest" })) },
+        BatchOp::Delete { key: "old".to_string() },
+    ];
+    neemo.batch(&ops).unwrap();
+
+    let admins = neemo.query_with_keys("role", json!("admin")).unwrap();
+    assert_eq!(admins.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), ["a"]);
+    assert_eq!(neemo.query("role", json!("guest")).unwrap().len(), 1);
+    assert!(neemo.get("old").is_none());
+
+    neemo.set_schema(Schema::new("user:").require("name", FieldType::String));
+    let invalid = [
+        BatchOp::Insert { key: "c".to_string(), doc: doc(json!({ "role": "guest" })) },
+        BatchOp::Insert { key: "user:d".to_string(), doc: doc(json!({ "role": "guest" })) },
+    ];
+    assert!(neemo.batch(&invalid).is_err());
+    assert!(neemo.get("c").is_none());
+    assert_eq!(neemo.query("role", json!("guest")).unwrap().len(), 1);
+}
+
+#[test]
+fn upsert_reports_whether_it_created_or_updated() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("role").unwrap();
+
+    assert_eq!(neemo.upsert("a", doc(json!({ "role": "guest" }))).unwrap(), UpsertResult::Created);
+    assert_eq!(neemo.query("role", json!("guest")).unwrap().len(), 1);
+
+    assert_eq!(neemo.upsert("a", doc(json!({ "role": "admin" }))).unwrap(), UpsertResult::Updated);
+    assert!(neemo.query("role", json!("guest")).unwrap().is_empty());
+    assert_eq!(neemo.query("role", json!("admin")).unwrap(), [doc(json!({ "role": "admin" }))]);
+    assert_eq!(neemo.count().unwrap(), 1);
+
+    neemo.insert_with_ttl("b", doc(json!({ "role": "guest" })), Duration::ZERO).unwrap();
+    assert_eq!(neemo.upsert("b", doc(json!({ "role": "guest" }))).unwrap(), UpsertResult::Created);
+}
+
+#[test]
+fn delete_many_counts_removed_documents_and_clears_their_entries() {
+    let neemo = Neemo::in_memory();
+    neemo.create_index("role").unwrap();
+    for (key, role) in [("a", "admin"), ("b", "guest"), ("c", "admin")] {
+        neemo.insert(key, doc(json!({ "role": role }))).unwrap();
+    }
+
+    assert_eq!(neemo.delete_many(&["a", "missing", "b", "a"]).unwrap(), 2);
+    assert!(neemo.get("a").is_none() && neemo.get("b").is_none());
+    let admins = neemo.query_with_keys("role", json!("admin")).unwrap();
+    assert_eq!(admins.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), ["c"]);
+    assert!(neemo.query("role", json!("guest")).unwrap().is_empty());
+    assert_eq!(neemo.delete_many(&[]).unwrap(), 0);
+}
+
+#[test]
+fn delete_many_removes_nothing_when_a_document_is_corrupt() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let docs = db.open_tree("docs").unwrap();
+    let neemo = Neemo::with_stores(docs.clone(), db.open_tree("index").unwrap());
+    neemo.insert("a", doc(json!({ "n": 1 }))).unwrap();
+    docs.insert("bad", b"not json".to_vec()).unwrap();
+
+    let result = neemo.delete_many(&["a", "bad"]);
+    assert!(matches!(result, Err(NeemoError::Corrupt(key)) if key == "bad"));
+    assert_eq!(neemo.get("a"), Some(doc(json!({ "n": 1 }))));
+}